@@ -0,0 +1,206 @@
+use std::f32::consts::PI;
+
+use crate::draw::{Context, LINE_HEIGHT};
+
+const FFT_SIZE: usize = 1024;
+const BANDS: usize = 64;
+const MIN_FREQ: f32 = 30.0;
+const MIN_DB: f32 = -72.0;
+/// Fraction of the level kept from one frame to the next when the signal decreases.
+const DECAY: f32 = 0.85;
+const PANEL_X: i32 = 8;
+/// Margin under the panel.
+const PANEL_BOTTOM: i32 = 10;
+const PANEL_HEIGHT: i32 = 100;
+const METER_WIDTH: i32 = 6;
+
+/// Computes peak and RMS levels per channel and a spectrum of the captured audio.
+pub struct Analyzer {
+	history: Vec<f32>,
+	pos: usize,
+	peak: [f32; 2],
+	rms: [f32; 2],
+	bands: [f32; BANDS],
+	rate: f32,
+	window: Vec<f32>,
+	re: Vec<f32>,
+	im: Vec<f32>,
+}
+
+impl Analyzer {
+	pub fn new() -> Self {
+		Self {
+			history: vec![0.0; FFT_SIZE],
+			pos: 0,
+			peak: [0.0; 2],
+			rms: [0.0; 2],
+			bands: [0.0; BANDS],
+			rate: 44100.0,
+			// Hann window
+			window: (0..FFT_SIZE)
+				.map(|i| 0.5 - 0.5 * (2.0 * PI * i as f32 / (FFT_SIZE - 1) as f32).cos())
+				.collect(),
+			re: vec![0.0; FFT_SIZE],
+			im: vec![0.0; FFT_SIZE],
+		}
+	}
+
+	/// Updates the levels and the spectrum with interleaved stereo samples.
+	pub fn feed(&mut self, samples: &[i16], rate: i32) {
+		self.rate = rate as f32;
+		let mut peak = [0f32; 2];
+		let mut sum = [0f32; 2];
+		for frame in samples.chunks_exact(2) {
+			let (l, r) = (frame[0] as f32 / 32768.0, frame[1] as f32 / 32768.0);
+			peak = [peak[0].max(l.abs()), peak[1].max(r.abs())];
+			sum = [sum[0] + l * l, sum[1] + r * r];
+			self.history[self.pos] = (l + r) / 2.0;
+			self.pos = (self.pos + 1) % FFT_SIZE;
+		}
+		let frames = (samples.len() / 2).max(1) as f32;
+		for c in 0..2 {
+			self.peak[c] = peak[c].max(self.peak[c] * DECAY);
+			self.rms[c] = (sum[c] / frames).sqrt().max(self.rms[c] * DECAY);
+		}
+		self.spectrum();
+	}
+
+	fn spectrum(&mut self) {
+		for i in 0..FFT_SIZE {
+			self.re[i] = self.history[(self.pos + i) % FFT_SIZE] * self.window[i];
+			self.im[i] = 0.0;
+		}
+		fft(&mut self.re, &mut self.im);
+		let nyquist = self.rate / 2.0;
+		let bin_width = self.rate / FFT_SIZE as f32;
+		for band in 0..BANDS {
+			// bands are spread logarithmically between MIN_FREQ and the Nyquist frequency
+			let f0 = MIN_FREQ * (nyquist / MIN_FREQ).powf(band as f32 / BANDS as f32);
+			let f1 = MIN_FREQ * (nyquist / MIN_FREQ).powf((band + 1) as f32 / BANDS as f32);
+			let b0 = ((f0 / bin_width) as usize).clamp(1, FFT_SIZE / 2 - 1);
+			let b1 = ((f1 / bin_width) as usize).clamp(b0 + 1, FFT_SIZE / 2);
+			let power = (b0..b1)
+				.map(|b| self.re[b] * self.re[b] + self.im[b] * self.im[b])
+				.fold(0.0, f32::max);
+			// normalize so that a full scale sine reaches 0dB
+			let magnitude = power.sqrt() / (FFT_SIZE as f32 / 4.0);
+			let level = level(magnitude);
+			self.bands[band] = level.max(self.bands[band] * DECAY);
+		}
+	}
+
+	/// Draws the spectrum and the meters in a panel at the bottom of the screen whose top left
+	/// corner is at `origin`.
+	pub fn draw(&self, ctx: &mut Context<'_, '_, '_>, origin: (i32, i32)) -> Result<(), String> {
+		let theme = ctx.theme;
+		let panel_width = ctx.screen.width as i32 - 2 * PANEL_X;
+		let panel_y = ctx.screen.height as i32 - PANEL_HEIGHT - PANEL_BOTTOM;
+		let (panel_x, panel_y) = (origin.0 + PANEL_X, origin.1 + panel_y);
+		ctx.draw_rect((panel_x, panel_y, panel_width as u32, PANEL_HEIGHT as u32), theme.screen)?;
+		let top = panel_y + LINE_HEIGHT;
+		let height = PANEL_HEIGHT - LINE_HEIGHT - 2;
+		ctx.draw_str("SPECTRUM", panel_x + 2, panel_y, theme.text_title, theme.text_title)?;
+
+		let meters_x = panel_x + panel_width - 4 * METER_WIDTH;
+		let band_width = (meters_x - panel_x - 4) / BANDS as i32;
+		for (i, &band) in self.bands.iter().enumerate() {
+			let h = (band * height as f32) as i32;
+			let x = panel_x + 2 + i as i32 * band_width;
+			let rect = (x, top + height - h, (band_width - 1).max(1) as u32, h as u32);
+			ctx.draw_rect(rect, theme.text_value)?;
+		}
+
+		for c in 0..2 {
+			let x = meters_x + c as i32 * 2 * METER_WIDTH;
+			ctx.draw_rect((x, top, METER_WIDTH as u32, height as u32), theme.velocity_bg)?;
+			let rms = (level(self.rms[c]) * height as f32) as i32;
+			ctx.draw_rect(
+				(x, top + height - rms, METER_WIDTH as u32, rms as u32),
+				theme.text_info,
+			)?;
+			let peak = (level(self.peak[c]) * height as f32) as i32;
+			ctx.draw_rect((x, top + height - peak, METER_WIDTH as u32, 2), theme.cursor)?;
+		}
+		Ok(())
+	}
+}
+
+/// Maps an amplitude to a level between 0 (MIN_DB) and 1 (0dB).
+fn level(amplitude: f32) -> f32 {
+	let db = 20.0 * amplitude.max(1e-6).log10();
+	((db - MIN_DB) / -MIN_DB).clamp(0.0, 1.0)
+}
+
+/// In-place iterative radix-2 FFT, the length must be a power of two.
+fn fft(re: &mut [f32], im: &mut [f32]) {
+	let n = re.len();
+	let mut j = 0;
+	for i in 1..n {
+		let mut bit = n >> 1;
+		while j & bit != 0 {
+			j ^= bit;
+			bit >>= 1;
+		}
+		j |= bit;
+		if i < j {
+			re.swap(i, j);
+			im.swap(i, j);
+		}
+	}
+	let mut len = 2;
+	while len <= n {
+		let angle = -2.0 * PI / len as f32;
+		let (w_re, w_im) = (angle.cos(), angle.sin());
+		for start in (0..n).step_by(len) {
+			let (mut u_re, mut u_im) = (1.0f32, 0.0f32);
+			for k in 0..len / 2 {
+				let (a, b) = (start + k, start + k + len / 2);
+				let t_re = re[b] * u_re - im[b] * u_im;
+				let t_im = re[b] * u_im + im[b] * u_re;
+				re[b] = re[a] - t_re;
+				im[b] = im[a] - t_im;
+				re[a] += t_re;
+				im[a] += t_im;
+				let next = u_re * w_re - u_im * w_im;
+				u_im = u_re * w_im + u_im * w_re;
+				u_re = next;
+			}
+		}
+		len <<= 1;
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn finds_the_bin_of_a_sine() {
+		let bin = 37;
+		let mut re: Vec<f32> =
+			(0..FFT_SIZE).map(|i| (2.0 * PI * (bin * i) as f32 / FFT_SIZE as f32).sin()).collect();
+		let mut im = vec![0.0; FFT_SIZE];
+		fft(&mut re, &mut im);
+		let power: Vec<f32> = re.iter().zip(&im).map(|(r, i)| r * r + i * i).collect();
+		let peak = (0..FFT_SIZE / 2).max_by(|a, b| power[*a].total_cmp(&power[*b])).unwrap();
+		assert_eq!(peak, bin);
+	}
+
+	#[test]
+	fn raises_the_band_of_a_sine() {
+		let mut analyzer = Analyzer::new();
+		let (rate, freq) = (44100, 1000.0);
+		let samples: Vec<i16> = (0..FFT_SIZE)
+			.flat_map(|i| {
+				let sample = (2.0 * PI * freq * i as f32 / rate as f32).sin() * 16000.0;
+				[sample as i16, sample as i16]
+			})
+			.collect();
+		analyzer.feed(&samples, rate);
+		let nyquist = rate as f32 / 2.0;
+		let band = ((freq / MIN_FREQ).ln() / (nyquist / MIN_FREQ).ln() * BANDS as f32) as usize;
+		let loudest =
+			(0..BANDS).max_by(|a, b| analyzer.bands[*a].total_cmp(&analyzer.bands[*b])).unwrap();
+		assert!(loudest.abs_diff(band) <= 1, "band {} instead of {}", loudest, band);
+	}
+}
@@ -1,13 +1,15 @@
 use sdl2::{
 	joystick::{HatState, Joystick},
 	keyboard::{Keycode, Mod},
-	render::Canvas,
+	rect::Rect,
+	render::{Canvas, Texture},
 	video::Window,
-	JoystickSubsystem,
+	AudioSubsystem, JoystickSubsystem,
 };
 use std::{
 	cmp::Ordering,
 	collections::HashMap,
+	path::PathBuf,
 	sync::{
 		atomic::{self, AtomicBool},
 		Arc,
@@ -17,22 +19,30 @@ use std::{
 };
 
 use crate::{
-	config::{self, Command, Config},
+	analyzer::Analyzer,
+	audio, capture,
+	config::{self, Command, Config, Scaling},
+	cpu::CpuUsage,
+	diagnostics::Diagnostics,
 	draw::{self, Context},
 	font,
+	learn::Learn,
 	m8::{self, M8},
+	macros::{self, Player, Recorder},
 	menu,
 	menu_tools::{
-		app_from_page, app_to_page, axes_from_page, buttons_from_page, clear_axes_page,
-		clear_buttons_page, clear_hats_page, clear_joystick_subpages, hats_from_page,
-		joystick_has_hats, m8_keys_from_page, m8_to_page, rm8_keys_from_page, rm8_to_page,
-		selected_joystick_config, selected_joystick_guid, selected_joystick_id, theme_from_page,
-		theme_to_page, update_axes_page, update_buttons_page, update_hats_page,
-		update_joystick_pages,
+		app_from_page, app_to_page, audio_from_page, audio_fx_from_page, audio_fx_to_page,
+		audio_to_page, axes_from_page, buttons_from_page, clear_axes_page, clear_buttons_page,
+		clear_hats_page, clear_joystick_subpages, hats_from_page, joystick_has_hats,
+		keys_from_page, keys_to_page, midi_from_page, midi_to_page, selected_joystick_config,
+		selected_joystick_guid, selected_joystick_id, theme_from_page, theme_to_page,
+		update_axes_page, update_buttons_page, update_hats_page, update_joystick_pages,
 	},
+	midi,
 	nav::{Action, Direction, Edit, Navigation, Page},
 	nav::{Input, Item},
 	remap::Remap,
+	status::Status,
 	value::Value,
 };
 
@@ -44,6 +54,11 @@ const KEY_OCT_INC: u8 = 1 << 2;
 const KEY_OCT_DEC: u8 = 1 << 3;
 const KEY_JAZZ: u8 = 1 << 4;
 const KEY_FAST: u8 = 1 << 5;
+/// Time without any change after which the frames are spaced out.
+const IDLE_AFTER: Duration = Duration::from_secs(1);
+const IDLE_INTERVAL: Duration = Duration::from_millis(40);
+/// Longest sleep between two reads of the input.
+const MAX_SLEEP: Duration = Duration::from_millis(20);
 
 pub struct App {
 	config: Config,
@@ -55,12 +70,39 @@ pub struct App {
 	action: Action,
 	in_config: bool,
 	remap: Option<Remap>,
+	learn: Option<Learn>,
 	keys: Value<u8>,
 	running: Arc<AtomicBool>,
 	defer: Option<Command>,
 	fps: usize,
 	fps_count: usize,
 	fps_ticks: time::Instant,
+	/// False when the audio loopback is left out, with `-noaudio` or for an M8 that is not
+	/// plugged here, so that the AUDIO page does not open it.
+	audio_loopback: bool,
+	video: Option<capture::VideoRecorder>,
+	/// Videos stopped, still being written.
+	videos_stopped: Vec<thread::JoinHandle<()>>,
+	video_audio: bool,
+	meters: bool,
+	analyzer: Analyzer,
+	samples: Vec<i16>,
+	screens: usize,
+	/// Geometry of each screen in the window, the largest of the M8s.
+	screen: m8::Screen,
+	focus: usize,
+	show_diagnostics: bool,
+	diagnostics: Diagnostics,
+	/// Set when the window has to be drawn again whatever the M8s sent.
+	redraw: bool,
+	last_change: time::Instant,
+	cpu: CpuUsage,
+	/// Part of the focused screen drawn for the last frame shown.
+	damage: Option<Rect>,
+	macro_recorder: Option<Recorder>,
+	macro_player: Option<Player>,
+	/// Line telling what was just done, like the files saved.
+	status: Status,
 }
 
 impl App {
@@ -76,18 +118,42 @@ impl App {
 			action: Action::None,
 			in_config: false,
 			remap: None,
+			learn: None,
 			keys: Value::<u8>::new(0),
 			running,
 			defer: None,
 			fps: config.app.fps,
 			fps_count: 0,
 			fps_ticks: time::Instant::now(),
+			audio_loopback: true,
+			video: None,
+			videos_stopped: Vec::new(),
+			video_audio: false,
+			meters: false,
+			analyzer: Analyzer::new(),
+			samples: Vec::new(),
+			screens: 1,
+			screen: m8::Screen::default(),
+			focus: 0,
+			show_diagnostics: false,
+			diagnostics: Diagnostics::new(),
+			redraw: true,
+			last_change: time::Instant::now(),
+			cpu: CpuUsage::new(),
+			damage: None,
+			macro_recorder: None,
+			macro_player: None,
+			status: Status::new(),
 			config,
 		}
 	}
 
-	pub fn build_menu(&mut self, m8: &M8) {
-		menu::build_menu(&mut self.menu, m8, &self.config);
+	pub fn set_audio_loopback(&mut self, enabled: bool) {
+		self.audio_loopback = enabled;
+	}
+
+	pub fn build_menu(&mut self, m8: &M8, audio: &AudioSubsystem) {
+		menu::build_menu(&mut self.menu, m8, audio, &self.config);
 	}
 
 	pub fn running(&self) -> bool {
@@ -115,9 +181,10 @@ impl App {
 		self.remap.is_some()
 	}
 
-	pub fn remap(&mut self, keycode: Keycode) -> bool {
+	/// Binds the key pressed or released, returns true once the remapping is over.
+	pub fn remap(&mut self, keycode: Keycode, keymod: Mod, released: bool) -> bool {
 		if let Some(ref mut r) = self.remap {
-			if r.remap(&mut self.menu, keycode) {
+			if r.key(&mut self.menu, config::Binding::new(keycode, keymod), released) {
 				r.abort(&mut self.menu);
 				self.remap = None;
 				return true;
@@ -125,6 +192,20 @@ impl App {
 		}
 		false
 	}
+	/// Returns true if the key leaves the remapping or the learning: a key of the `ESCAPE`
+	/// command, unless the key of that command is the one being remapped.
+	pub fn aborts_input_mode(&self, keycode: Keycode, keymod: Mod) -> bool {
+		let escape = matches!(self.config.key_pressed(keycode, keymod), Some((Command::Escape, _)));
+		escape && self.remap.as_ref().and_then(Remap::command) != Some(Command::Escape)
+	}
+
+	/// Leaves the remapping or the learning, whatever else the `ESCAPE` command does.
+	pub fn abort_input_mode(&mut self, m8: &mut M8) {
+		self.cancel_remap_mode();
+		self.cancel_learn_mode();
+		m8.refresh();
+	}
+
 	pub fn cancel_remap_mode(&mut self) {
 		if let Some(mut r) = self.remap.take() {
 			r.abort(&mut self.menu);
@@ -132,6 +213,28 @@ impl App {
 		}
 	}
 
+	pub fn learn_mode(&self) -> bool {
+		self.learn.is_some()
+	}
+
+	/// Binds the note or the controller pressed, returns true once the learning is over.
+	pub fn learn(&mut self, message: midi::Message) -> bool {
+		if let (Some(l), Some(control)) = (self.learn.as_mut(), message.pressed()) {
+			if l.learn(&mut self.menu, control) {
+				l.abort(&mut self.menu);
+				self.learn = None;
+				return true;
+			}
+		}
+		false
+	}
+
+	pub fn cancel_learn_mode(&mut self) {
+		if let Some(mut l) = self.learn.take() {
+			l.abort(&mut self.menu);
+		}
+	}
+
 	pub fn config(&self) -> &Config {
 		&self.config
 	}
@@ -141,58 +244,44 @@ impl App {
 	}
 
 	pub fn handle_key(&mut self, m8: &mut M8, keycode: Keycode, keymod: Mod, clear: bool) {
-		let f = if clear { Value::clr_bit } else { Value::set_bit };
 		if clear && *m8.keyjazz && self.config.keyjazz.contains_key(&config::Keycode(keycode)) {
 			m8.set_note_off()
 		}
-		if keycode == *self.config.m8.up {
-			f(&mut m8.keys, m8::KEY_UP);
-		} else if keycode == *self.config.m8.down {
-			f(&mut m8.keys, m8::KEY_DOWN);
-		} else if keycode == *self.config.m8.left {
-			f(&mut m8.keys, m8::KEY_LEFT);
-		} else if keycode == *self.config.m8.right {
-			f(&mut m8.keys, m8::KEY_RIGHT);
-		} else if keycode == *self.config.m8.edit {
-			f(&mut m8.keys, m8::KEY_EDIT);
-		} else if keycode == *self.config.m8.option {
-			f(&mut m8.keys, m8::KEY_OPTION);
-		} else if keycode == *self.config.m8.shift {
-			f(&mut m8.keys, m8::KEY_SHIFT);
-		} else if keycode == *self.config.m8.play {
-			f(&mut m8.keys, m8::KEY_PLAY);
-		} else if keycode == *self.config.rm8.octave_minus {
-			f(&mut self.keys, KEY_OCT_DEC);
-		} else if keycode == *self.config.rm8.octave_plus {
-			f(&mut self.keys, KEY_OCT_INC);
-		} else if keycode == *self.config.rm8.velocity_minus {
-			if clear {
-				f(&mut self.keys, KEY_VEL_DEC | KEY_FAST);
-			} else {
-				f(
-					&mut self.keys,
-					KEY_VEL_DEC
-						| if keymod.intersects(Mod::LSHIFTMOD | Mod::RSHIFTMOD) {
-							KEY_FAST
-						} else {
-							0
-						},
-				);
+		if clear {
+			for cmd in self.config.key_released(keycode) {
+				self.handle_cmd(m8, Some((cmd, true)));
 			}
-		} else if keycode == *self.config.rm8.velocity_plus {
-			if clear {
-				f(&mut self.keys, KEY_VEL_INC | KEY_FAST);
-			} else {
-				f(
-					&mut self.keys,
-					KEY_VEL_INC
-						| if keymod.intersects(Mod::LSHIFTMOD | Mod::RSHIFTMOD) {
-							KEY_FAST
-						} else {
-							0
-						},
-				);
+		} else if let Some((cmd, _)) = self.config.key_pressed(keycode, keymod) {
+			let fast = keymod.intersects(Mod::LSHIFTMOD | Mod::RSHIFTMOD);
+			self.run_command(m8, cmd, false, fast);
+			self.record_macro(m8, cmd, false);
+		} else if let Some(i) = self.config.macro_pressed(keycode, keymod) {
+			self.play_macro(i);
+		}
+	}
+
+	/// Runs the commands bound to the notes and the controllers of a MIDI device, and plays the
+	/// other notes through keyjazz.
+	pub fn handle_midi(&mut self, m8: &mut M8, message: midi::Message) {
+		let (midi::Message::NoteOn { channel, .. }
+		| midi::Message::NoteOff { channel, .. }
+		| midi::Message::Control { channel, .. }) = message;
+		if !self.config.midi.listens(channel) {
+			return;
+		}
+		if let Some(cmd) = self.config.midi.command(&message) {
+			self.handle_cmd(m8, Some(cmd));
+			return;
+		}
+		let midi = &self.config.midi;
+		match message {
+			midi::Message::NoteOn { note, velocity, .. } if !self.in_config => {
+				m8.play_note(midi.note(note, *m8.octave), midi.velocity.then_some(velocity));
+			}
+			midi::Message::NoteOff { note, .. } => {
+				m8.stop_note(midi.note(note, *m8.octave));
 			}
+			_ => {}
 		}
 	}
 
@@ -268,33 +357,123 @@ impl App {
 		None
 	}
 
+	/// Runs a command from the keyboard, a joystick or a macro, pressed or released.
 	pub fn handle_cmd(&mut self, m8: &mut M8, cmd: Option<(Command, bool)>) {
 		if let Some((cmd, clear)) = cmd {
-			let f = if clear { Value::clr_bit } else { Value::set_bit };
-			match cmd {
-				Command::Up => f(&mut m8.keys, m8::KEY_UP),
-				Command::Down => f(&mut m8.keys, m8::KEY_DOWN),
-				Command::Left => f(&mut m8.keys, m8::KEY_LEFT),
-				Command::Right => f(&mut m8.keys, m8::KEY_RIGHT),
-				Command::Edit => f(&mut m8.keys, m8::KEY_EDIT),
-				Command::r#Option => f(&mut m8.keys, m8::KEY_OPTION),
-				Command::Shift => f(&mut m8.keys, m8::KEY_SHIFT),
-				Command::Play => f(&mut m8.keys, m8::KEY_PLAY),
-
-				Command::Keyjazz => f(&mut m8.keys, KEY_JAZZ),
-				Command::VelocityMinus => f(&mut m8.keys, KEY_VEL_DEC),
-				Command::VelocityPlus => f(&mut m8.keys, KEY_VEL_INC),
-				Command::OctaveMinus => f(&mut m8.keys, KEY_OCT_DEC),
-				Command::OctavePlus => f(&mut m8.keys, KEY_OCT_INC),
-				Command::Config => self.start_config_mode(),
-				Command::Escape | Command::Fullscreen | Command::Reset | Command::ResetFull => {
-					self.defer.replace(cmd);
+			self.run_command(m8, cmd, clear, false);
+			self.record_macro(m8, cmd, clear);
+		}
+	}
+
+	/// Runs a command whatever the input it comes from. The M8 keys, the velocity and the octave
+	/// are held until released, the other commands act when pressed.
+	fn run_command(&mut self, m8: &mut M8, cmd: Command, clear: bool, fast: bool) {
+		let f = if clear { Value::clr_bit } else { Value::set_bit };
+		let fast = if fast { KEY_FAST } else { 0 };
+		match cmd {
+			// an axis back to its center
+			Command::None => m8.keys.clr_bit(m8::KEY_DIR),
+			Command::Up => f(&mut m8.keys, m8::KEY_UP),
+			Command::Down => f(&mut m8.keys, m8::KEY_DOWN),
+			Command::Left => f(&mut m8.keys, m8::KEY_LEFT),
+			Command::Right => f(&mut m8.keys, m8::KEY_RIGHT),
+			Command::Edit => f(&mut m8.keys, m8::KEY_EDIT),
+			Command::r#Option => f(&mut m8.keys, m8::KEY_OPTION),
+			Command::Shift => f(&mut m8.keys, m8::KEY_SHIFT),
+			Command::Play => f(&mut m8.keys, m8::KEY_PLAY),
+			Command::Keyjazz => f(&mut self.keys, KEY_JAZZ),
+			Command::OctaveMinus => f(&mut self.keys, KEY_OCT_DEC),
+			Command::OctavePlus => f(&mut self.keys, KEY_OCT_INC),
+			Command::VelocityMinus if clear => f(&mut self.keys, KEY_VEL_DEC | KEY_FAST),
+			Command::VelocityMinus => f(&mut self.keys, KEY_VEL_DEC | fast),
+			Command::VelocityPlus if clear => f(&mut self.keys, KEY_VEL_INC | KEY_FAST),
+			Command::VelocityPlus => f(&mut self.keys, KEY_VEL_INC | fast),
+			_ if clear => {}
+			Command::Config | Command::Reset | Command::ResetFull if self.in_config => {}
+			Command::Config
+			| Command::Escape
+			| Command::Fullscreen
+			| Command::Reset
+			| Command::ResetFull
+			| Command::Screenshot
+			| Command::Record
+			| Command::RecordAudio
+			| Command::Layout
+			| Command::Focus => {
+				self.defer.replace(cmd);
+			}
+			Command::Meters => self.meters = !self.meters,
+			Command::GainMinus | Command::GainPlus | Command::Mute => {
+				self.audio_fx_command(m8, cmd);
+			}
+			Command::Diagnostics => self.toggle_diagnostics(m8),
+			Command::MacroRecord => self.toggle_macro_record(),
+			Command::MacroPlay => self.play_recorded_macro(),
+		}
+	}
+
+	fn record_macro(&mut self, m8: &M8, cmd: Command, clear: bool) {
+		if let Some(ref mut recorder) = self.macro_recorder {
+			if !self.in_config {
+				recorder.command(cmd, *m8.keys, clear);
+			}
+		}
+	}
+
+	fn toggle_macro_record(&mut self) {
+		match self.macro_recorder.take() {
+			None => {
+				self.macro_recorder = Some(Recorder::default());
+				self.status.show("macro recording started".to_string());
+			}
+			Some(recorder) => {
+				let steps = recorder.finish();
+				self.status.show(format!("macro recorded: {} steps", steps.len()));
+				match self.config.macros.iter_mut().find(|m| m.name == macros::RECORDED) {
+					Some(m) => m.steps = steps,
+					None => self.config.macros.push(config::MacroConfig {
+						name: macros::RECORDED.to_string(),
+						steps,
+						..Default::default()
+					}),
 				}
-				Command::None => m8.keys.clr_bit(m8::KEY_DIR),
 			}
 		}
 	}
 
+	/// Plays the last recorded macro, or the first one of the config.
+	fn play_recorded_macro(&mut self) {
+		let i = self.config.macros.iter().position(|m| m.name == macros::RECORDED);
+		if let Some(i) = i.or((!self.config.macros.is_empty()).then_some(0)) {
+			self.play_macro(i);
+		}
+	}
+
+	fn play_macro(&mut self, i: usize) {
+		if self.in_config || self.macro_recorder.is_some() {
+			return;
+		}
+		let m = &self.config.macros[i];
+		if !m.steps.is_empty() {
+			self.macro_player = Some(Player::new(m.steps.clone(), m.delay));
+		}
+	}
+
+	/// Presses or releases the next step of the macro playing, if any.
+	fn step_macro(&mut self, m8: &mut M8) {
+		match self.macro_player.as_mut().and_then(Player::step) {
+			Some((config::MacroStep::Keys(keys), false)) => m8.keys.set(*m8.keys | keys),
+			Some((config::MacroStep::Keys(keys), true)) => m8.keys.set(*m8.keys & !keys),
+			Some((config::MacroStep::Command(cmd), release)) => {
+				self.run_command(m8, cmd, release, false)
+			}
+			None => {}
+		}
+		if self.macro_player.as_ref().is_some_and(Player::done) {
+			self.macro_player = None;
+		}
+	}
+
 	pub fn add_joystick(&mut self, joystick_subsystem: &JoystickSubsystem, which: u32) {
 		if self.menu.main_page_short_name() == 'J' {
 			self.menu.dirty();
@@ -330,6 +509,7 @@ impl App {
 	}
 
 	pub fn process_key(&mut self, m8: &mut M8) {
+		self.step_macro(m8);
 		let now = time::Instant::now();
 		if now - self.config_ticks > Duration::from_millis(self.config.app.key_sensibility) {
 			if self.in_config {
@@ -402,6 +582,7 @@ impl App {
 		canvas: &mut Canvas<Window>,
 		m8: &mut M8,
 		joystick_subsystem: &JoystickSubsystem,
+		audio_subsystem: &AudioSubsystem,
 	) -> Result<(), String> {
 		let mut dirty = false;
 		let page = self.menu.page();
@@ -410,27 +591,27 @@ impl App {
 				let old_zoom = self.config.app.zoom;
 				self.config.app = app_from_page(page);
 				if self.config.app.zoom != old_zoom {
-					draw::zoom_window(canvas.window_mut(), self.config.app.zoom);
+					draw::zoom_window(
+						canvas.window_mut(),
+						self.config.app.zoom,
+						self.logical_size(),
+					);
 				}
-				if let Some(Item::Input(_, Input::Device(d))) = page.items().nth(7) {
+				if let Some(Item::Input(_, Input::Device(d))) = page.items().nth(10) {
 					let device = d.value();
 					if device != m8.device_name().as_deref() {
 						if let Some(dev) = device {
-							if let Ok(new_m8) = M8::open(dev) {
+							if let Ok(mut new_m8) = M8::open(dev) {
+								if let Some(audio) = m8.take_audio() {
+									new_m8.connect_audio(audio);
+								}
+								new_m8.set_screen_config(self.config.screen);
 								*m8 = new_m8;
 								m8.enable_and_reset_display()?;
 							}
 						}
 					}
 				}
-				if let Some(Item::Input(_, Input::Audio(d))) = page.items().nth(8) {
-					let device = d.value();
-					if device != m8.capture_device_name().as_deref() {
-						if let Some(dev) = device {
-							m8.reopen_audio(dev.to_string())?;
-						}
-					}
-				}
 				m8.set_reconnect(self.config.app.reconnect);
 				m8.keyjazz.set(!self.config.overlap);
 				dirty = true;
@@ -439,11 +620,29 @@ impl App {
 				self.config.theme = theme_from_page(page);
 				dirty = true;
 			}
-			'K' => {
-				self.config.m8 = m8_keys_from_page(page);
+			'S' => {
+				self.config.audio = audio_from_page(page);
+				if self.audio_loopback {
+					// the recording stops with the devices it is made from
+					if m8.closes_audio(&self.config.audio) {
+						self.video_audio = false;
+						self.report_audio_recording(m8.stop_audio_recording());
+					}
+					if let Err(e) = m8.configure_audio(audio_subsystem, &self.config.audio) {
+						eprintln!("could not open audio device: {}", e);
+					}
+					m8.set_audio_fx(&self.config.audio_fx);
+				}
 			}
-			'R' => {
-				self.config.rm8 = rm8_keys_from_page(page);
+			'F' => {
+				self.config.audio_fx = audio_fx_from_page(page);
+				m8.set_audio_fx(&self.config.audio_fx);
+			}
+			'K' | 'R' | 'P' => {
+				keys_from_page(page, &mut self.config);
+			}
+			'M' | 'N' | 'I' => {
+				midi_from_page(page, &mut self.config.midi);
 			}
 			'J' => {
 				dirty = true;
@@ -489,18 +688,27 @@ impl App {
 				if let Some(sub) = page.find('T') {
 					self.config.theme = theme_from_page(sub);
 				}
+				if let Some(sub) = page.find('S') {
+					self.config.audio = audio_from_page(sub);
+				}
+				if let Some(sub) = page.find('F') {
+					self.config.audio_fx = audio_fx_from_page(sub);
+				}
 				self.config.write(config_file.unwrap_or(CONFIG_FILE))?;
 			}
-			'T' => {
+			'T' | 'S' | 'F' => {
 				self.config.write(config_file.unwrap_or(CONFIG_FILE))?;
 			}
 			'K' => {
 				if let Some(sub) = page.find('R') {
-					self.config.rm8 = rm8_keys_from_page(sub);
+					keys_from_page(sub, &mut self.config);
+				}
+				if let Some(sub) = page.find('P') {
+					keys_from_page(sub, &mut self.config);
 				}
 				self.config.write(config_file.unwrap_or(CONFIG_FILE))?;
 			}
-			'R' => {
+			'R' | 'P' | 'M' | 'N' | 'I' => {
 				self.config.write(config_file.unwrap_or(CONFIG_FILE))?;
 			}
 			'J' => {
@@ -561,15 +769,25 @@ impl App {
 				self.config.theme = cfg.theme;
 				theme_to_page(page, &self.config);
 			}
-			'K' => {
+			'S' => {
 				let page = self.menu.page_mut();
-				self.config.m8 = cfg.m8;
-				m8_to_page(page, &self.config);
+				self.config.audio = cfg.audio;
+				audio_to_page(page, &self.config);
 			}
-			'R' => {
+			'F' => {
 				let page = self.menu.page_mut();
-				self.config.rm8 = cfg.rm8;
-				rm8_to_page(page, &self.config);
+				self.config.audio_fx = cfg.audio_fx;
+				audio_fx_to_page(page, &self.config);
+			}
+			'K' | 'R' | 'P' => {
+				let page = self.menu.page_mut();
+				keys_to_page(page, &cfg);
+				keys_from_page(page, &mut self.config);
+			}
+			'M' | 'N' | 'I' => {
+				let page = self.menu.page_mut();
+				midi_to_page(page, &cfg);
+				midi_from_page(page, &mut self.config.midi);
 			}
 			'J' => {
 				if let Some(guid) = selected_joystick_guid(&self.menu) {
@@ -642,13 +860,19 @@ impl App {
 		canvas: &mut Canvas<Window>,
 		m8: &mut M8,
 		joystick_subsystem: &JoystickSubsystem,
+		audio_subsystem: &AudioSubsystem,
 		config_file: &Option<String>,
 	) -> Result<(), String> {
 		match self.action {
-			Action::Modified => self.action_modified(canvas, m8, joystick_subsystem)?,
+			Action::Modified => {
+				self.action_modified(canvas, m8, joystick_subsystem, audio_subsystem)?
+			}
 			Action::Do("SAVE") => self.action_save(config_file.as_deref())?,
 			Action::Do("RESET") => self.action_reset(config_file.as_deref(), joystick_subsystem)?,
-			Action::Do("REMAP") => self.remap = Some(Remap::new(&mut self.menu)),
+			Action::Do("REMAP") => self.remap = Some(Remap::new(&mut self.menu, &self.config)),
+			Action::Do("ADD KEY") => self.remap = Some(Remap::add(&mut self.menu, &self.config)),
+			Action::Do("LEARN") => self.learn = Some(Learn::new(&mut self.menu)),
+			Action::Do("LEARN ONE") => self.learn = Some(Learn::one(&mut self.menu)),
 			Action::Do(_) => unimplemented!(),
 			Action::None => {}
 		}
@@ -679,17 +903,147 @@ impl App {
 		Ok(())
 	}
 
+	pub fn render_audio_stats(
+		&self,
+		ctx: &mut Context<'_, '_, '_>,
+		stats: Option<audio::Stats>,
+	) -> Result<(), String> {
+		if let (true, Some(stats)) = (self.config.audio.show_stats, stats) {
+			let s = format!(
+				"{:3}ms {:3}u {:3}o",
+				stats.latency.min(999),
+				stats.underruns.min(999),
+				stats.overruns.min(999)
+			);
+			let width = font::width(s.len() - 1);
+			let x = ctx.screen.width as i32 - width;
+			let fg = self.config.theme.text_default;
+			ctx.draw_rect(
+				(x, 0, width as u32, draw::LINE_HEIGHT as u32),
+				self.config.theme.screen,
+			)?;
+			ctx.draw_str(&s, x, 0, fg, fg)?;
+		}
+		Ok(())
+	}
+
+	fn audio_fx_command(&mut self, m8: &mut M8, cmd: Command) {
+		let fx = &mut self.config.audio_fx;
+		match cmd {
+			Command::GainMinus => fx.gain = (fx.gain - 1).max(-24),
+			Command::GainPlus => fx.gain = (fx.gain + 1).min(24),
+			Command::Mute => fx.mute = !fx.mute,
+			_ => return,
+		}
+		m8.set_audio_fx(&self.config.audio_fx);
+		if let Some(page) = self.menu.find_mut('C').and_then(|page| page.find_mut('F')) {
+			audio_fx_to_page(page, &self.config);
+		}
+	}
+
+	pub fn meters(&self) -> bool {
+		self.meters
+	}
+
+	/// Draws the level meters and the spectrum of the audio captured since the last call.
+	pub fn render_meters(
+		&mut self,
+		ctx: &mut Context<'_, '_, '_>,
+		m8: &mut M8,
+		origin: (i32, i32),
+	) -> Result<(), String> {
+		if let Some(rate) = m8.audio_samples(&mut self.samples) {
+			self.analyzer.feed(&self.samples, rate);
+			self.analyzer.draw(ctx, origin)?;
+		}
+		Ok(())
+	}
+
+	fn toggle_diagnostics(&mut self, m8: &M8) {
+		self.show_diagnostics = !self.show_diagnostics;
+		self.diagnostics.reset(m8.link_stats());
+	}
+
+	pub fn diagnostics(&self) -> bool {
+		self.show_diagnostics
+	}
+
+	/// Draws the health of the link with the M8.
+	pub fn render_diagnostics(
+		&mut self,
+		ctx: &mut Context<'_, '_, '_>,
+		m8: &M8,
+		origin: (i32, i32),
+	) -> Result<(), String> {
+		let stats = m8.link_stats();
+		self.diagnostics.update(stats);
+		self.diagnostics.draw(ctx, &stats, origin, &self.cpu, self.damage)
+	}
+
+	pub fn status_shown(&self) -> bool {
+		self.status.message().is_some()
+	}
+
+	/// Draws the status line over the bottom of the focused screen.
+	pub fn render_status(
+		&self,
+		ctx: &mut Context<'_, '_, '_>,
+		origin: (i32, i32),
+	) -> Result<(), String> {
+		self.status.draw(ctx, origin)
+	}
+
+	/// Returns true once it is time for the next frame, sleeping otherwise. The frames are
+	/// spaced out while nothing changes.
 	pub fn sync(&mut self) -> bool {
-		let now = time::Instant::now();
-		if now - self.frame_ticks > Duration::from_millis(15) {
-			self.frame_ticks = now;
+		let idle = !self.redraw && self.last_change.elapsed() > IDLE_AFTER;
+		let interval = if idle {
+			IDLE_INTERVAL
+		} else {
+			Duration::from_secs_f64(1.0 / self.config.app.fps.max(1) as f64)
+		};
+		let elapsed = self.frame_ticks.elapsed();
+		if elapsed >= interval {
+			self.frame_ticks = time::Instant::now();
 			return true;
 		}
-		let fps_sleep = (1.0 / self.config.app.fps as f64 * 1000.0) as u64;
-		thread::sleep(Duration::from_millis(fps_sleep));
+		// the input is read between the frames
+		thread::sleep((interval - elapsed).min(MAX_SLEEP));
 		false
 	}
 
+	/// Asks for the window to be drawn again, after an event.
+	pub fn request_redraw(&mut self) {
+		self.redraw = true;
+	}
+
+	/// Returns true if the window has to be drawn again: a shown screen is `damaged`, an overlay
+	/// or the menu is shown, or a redraw was requested.
+	pub fn needs_redraw(&mut self, damaged: bool) -> bool {
+		let status = self.status.update() || self.status_shown();
+		let redraw = damaged
+			|| self.redraw
+			|| self.in_config
+			|| self.meters
+			|| self.show_diagnostics
+			|| status;
+		self.redraw = false;
+		if redraw {
+			self.last_change = time::Instant::now();
+			self.cpu.activity();
+		}
+		self.cpu.update();
+		redraw
+	}
+
+	pub fn cpu(&self) -> &CpuUsage {
+		&self.cpu
+	}
+
+	pub fn set_damage(&mut self, damage: Option<Rect>) {
+		self.damage = damage;
+	}
+
 	pub fn escape_command(
 		&mut self,
 		m8: &mut M8,
@@ -700,6 +1054,8 @@ impl App {
 		} else if self.config_mode() {
 			if self.remap_mode() {
 				self.cancel_remap_mode();
+			} else if self.learn_mode() {
+				self.cancel_learn_mode();
 			} else {
 				self.cancel_config_mode();
 			}
@@ -710,14 +1066,253 @@ impl App {
 		Ok(())
 	}
 
-	pub fn handle_defer(&mut self, m8: &mut M8, canvas: &mut Canvas<Window>) -> Result<(), String> {
+	/// Runs the deferred command on the focused M8 and screen.
+	pub fn handle_defer(
+		&mut self,
+		m8s: &mut [M8],
+		canvas: &mut Canvas<Window>,
+		textures: &mut [Texture<'_>],
+	) -> Result<(), String> {
+		let (m8, texture) = (&mut m8s[self.focus], &mut textures[self.focus]);
+		if self.defer.is_some() {
+			self.redraw = true;
+		}
 		match self.defer.take() {
+			Some(Command::Config) => {
+				self.start_config_mode();
+				m8.reset_display()?;
+			}
 			Some(Command::Escape) => self.escape_command(m8, canvas)?,
 			Some(Command::Fullscreen) => draw::toggle_fullscreen(canvas)?,
 			Some(Command::Reset) => m8.reset(false)?,
 			Some(Command::ResetFull) => m8.reset(true)?,
+			Some(Command::Screenshot) => self.screenshot(canvas, texture)?,
+			Some(Command::Record) => self.toggle_video(m8),
+			Some(Command::RecordAudio) => self.toggle_audio_recording(m8),
+			Some(Command::Layout) => {
+				self.config.layout = self.config.layout.next();
+				self.set_screens(canvas, self.screens)?;
+			}
+			Some(Command::Focus) => self.focus_next(m8s),
 			Some(_) | None => {}
 		}
 		Ok(())
 	}
+
+	pub fn focus(&self) -> usize {
+		self.focus
+	}
+
+	/// Sizes the window for the given number of screens with the configured layout.
+	pub fn set_screens(
+		&mut self,
+		canvas: &mut Canvas<Window>,
+		screens: usize,
+	) -> Result<(), String> {
+		self.screens = screens.max(1);
+		self.focus = self.focus.min(self.screens - 1);
+		if !draw::is_fullscreen(canvas) {
+			draw::zoom_window(canvas.window_mut(), self.config.app.zoom, self.logical_size());
+		}
+		self.apply_scaling(canvas)
+	}
+
+	/// Scales the screens to the window as configured.
+	pub fn apply_scaling(&self, canvas: &mut Canvas<Window>) -> Result<(), String> {
+		let (width, height) = self.logical_size();
+		match self.config.app.scaling {
+			Scaling::Integer | Scaling::Fit => {
+				canvas.set_logical_size(width, height).map_err(|e| e.to_string())?;
+				canvas.set_integer_scale(self.config.app.scaling == Scaling::Integer)
+			}
+			Scaling::Stretch => {
+				// without a logical size, the scale alone maps the screens to the whole window
+				canvas.set_logical_size(0, 0).map_err(|e| e.to_string())?;
+				let (w, h) = canvas.output_size()?;
+				canvas.set_scale(w as f32 / width as f32, h as f32 / height as f32)
+			}
+		}
+	}
+
+	/// Resizes the window when the size of the screens changes.
+	pub fn set_screen(
+		&mut self,
+		canvas: &mut Canvas<Window>,
+		screen: m8::Screen,
+	) -> Result<(), String> {
+		if (screen.width, screen.height) == (self.screen.width, self.screen.height) {
+			return Ok(());
+		}
+		self.screen = screen;
+		self.set_screens(canvas, self.screens)
+	}
+
+	pub fn screen(&self) -> m8::Screen {
+		self.screen
+	}
+
+	fn logical_size(&self) -> (u32, u32) {
+		let (cols, rows) = draw::layout_grid(self.config.layout, self.screens);
+		(self.screen.width * cols, self.screen.height * rows)
+	}
+
+	/// Returns the screens to show and where to draw them.
+	pub fn tiles(&self) -> Vec<(usize, Rect)> {
+		let size = (self.screen.width, self.screen.height);
+		draw::layout_tiles(self.config.layout, self.screens, self.focus, size)
+	}
+
+	/// Moves the input and the audio loopback to the next M8.
+	fn focus_next(&mut self, m8s: &mut [M8]) {
+		if m8s.len() < 2 {
+			return;
+		}
+		let old = self.focus;
+		self.focus = (old + 1) % m8s.len();
+		// release whatever was held on the previous M8
+		self.keys.set(0);
+		m8s[old].set_note_off();
+		m8s[old].refresh();
+		if let Some(audio) = m8s[old].take_audio() {
+			m8s[self.focus].connect_audio(audio);
+		}
+		self.diagnostics.reset(m8s[self.focus].link_stats());
+	}
+
+	fn screenshot(
+		&mut self,
+		canvas: &mut Canvas<Window>,
+		texture: &mut Texture<'_>,
+	) -> Result<(), String> {
+		let pixels = draw::read_pixels(canvas, texture)?;
+		let query = texture.query();
+		match capture::screenshot(
+			&self.config.capture.directory,
+			&pixels,
+			query.width,
+			query.height,
+			self.capture_zoom(),
+		) {
+			Ok(file) => self.status.show(format!("screenshot saved to {}", file.display())),
+			Err(e) => eprintln!("screenshot failed: {}", e),
+		}
+		Ok(())
+	}
+
+	fn capture_zoom(&self) -> u32 {
+		if self.config.capture.zoom {
+			self.config.app.zoom
+		} else {
+			1
+		}
+	}
+
+	fn toggle_video(&mut self, m8: &mut M8) {
+		match self.video.take() {
+			Some(video) => {
+				self.stop_video(video, "");
+				if self.video_audio {
+					self.video_audio = false;
+					self.report_audio_recording(m8.stop_audio_recording());
+				}
+			}
+			None => match capture::VideoRecorder::start(
+				&self.config.capture.directory,
+				self.config.capture.video,
+				m8.screen().width,
+				m8.screen().height,
+				self.capture_zoom(),
+			) {
+				Ok(video) => {
+					// the audio is saved next to the video, under the same name
+					if self.config.capture.audio && !m8.audio_recording() {
+						match m8.record_audio(&video.path().with_extension("wav")) {
+							Ok(()) => self.video_audio = true,
+							Err(e) => eprintln!("audio recording failed: {}", e),
+						}
+					}
+					self.video = Some(video);
+				}
+				Err(e) => eprintln!("video recording failed: {}", e),
+			},
+		}
+	}
+
+	/// Lets the video be written in the background, the display going on meanwhile.
+	fn stop_video(&mut self, video: capture::VideoRecorder, reason: &'static str) {
+		let dropped = video.dropped();
+		self.videos_stopped.retain(|thread| !thread.is_finished());
+		let status = self.status.sender();
+		self.videos_stopped.push(video.stop(move |result| {
+			let message = match result {
+				Ok(path) if dropped > 0 => {
+					format!(
+						"{}video saved to {}, {} frames dropped",
+						reason,
+						path.display(),
+						dropped
+					)
+				}
+				Ok(path) => format!("{}video saved to {}", reason, path.display()),
+				Err(e) => {
+					eprintln!("video recording failed: {}", e);
+					return;
+				}
+			};
+			// the app may be gone once the video is written
+			let _ = status.send(message);
+		}));
+	}
+
+	fn toggle_audio_recording(&mut self, m8: &mut M8) {
+		if m8.audio_recording() {
+			self.video_audio = false;
+			self.report_audio_recording(m8.stop_audio_recording());
+		} else if let Err(e) = capture::new_file(&self.config.capture.directory, "wav")
+			.and_then(|file| m8.record_audio(&file))
+		{
+			eprintln!("audio recording failed: {}", e);
+		}
+	}
+
+	fn report_audio_recording(&mut self, result: Option<Result<PathBuf, String>>) {
+		match result {
+			Some(Ok(path)) => self.status.show(format!("audio saved to {}", path.display())),
+			Some(Err(e)) => eprintln!("audio recording failed: {}", e),
+			None => {}
+		}
+	}
+
+	pub fn record_frame(
+		&mut self,
+		canvas: &mut Canvas<Window>,
+		texture: &mut Texture<'_>,
+	) -> Result<(), String> {
+		if let Some(ref mut video) = self.video {
+			let query = texture.query();
+			if video.size() != (query.width, query.height) {
+				// the frames of a video all have the same size
+				if let Some(video) = self.video.take() {
+					self.stop_video(video, "screen resized, ");
+				}
+				return Ok(());
+			}
+			if video.wants_frame() {
+				if let Err(e) = video.push(draw::read_pixels(canvas, texture)?) {
+					eprintln!("video recording failed: {}", e);
+					self.video = None;
+				}
+			}
+		}
+		Ok(())
+	}
+}
+
+impl Drop for App {
+	fn drop(&mut self) {
+		// the videos stopped last may still be written
+		for thread in self.videos_stopped.drain(..) {
+			let _ = thread.join();
+		}
+	}
 }
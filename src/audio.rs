@@ -1,87 +1,243 @@
-use sdl2::audio::{AudioCallback, AudioDevice, AudioSpecDesired};
+use sdl2::{
+	audio::{AudioCallback, AudioDevice, AudioSpecDesired},
+	AudioSubsystem,
+};
 
-use std::sync::mpsc;
+use std::{
+	path::{Path, PathBuf},
+	sync::{
+		atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering},
+		Arc, Mutex,
+	},
+};
+
+use crate::{
+	config::{AudioConfig, AudioFxConfig},
+	dsp::{Chain, Params},
+	ring::{self, Consumer, Producer},
+	wav::WavRecorder,
+};
+
+const CHANNELS: u8 = 2;
+
+#[derive(Default)]
+struct Counters {
+	underruns: AtomicU64,
+	overruns: AtomicU64,
+	latency: AtomicU32,
+}
+
+/// State of the loopback, see `Audio::stats`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Stats {
+	/// Number of times the playback ran out of samples and played silence.
+	pub underruns: u64,
+	/// Number of times samples were dropped because the loopback or the recording lagged behind.
+	pub overruns: u64,
+	/// Samples buffered in the loopback, in milliseconds.
+	pub latency: u32,
+}
 
 struct Capture {
-	done_sender: mpsc::Sender<Vec<i16>>,
-	size: usize,
+	producer: Producer,
+	analysis: Producer,
+	/// Copy of the samples for the WAV recorder, pushed while `recording` is set.
+	record: Producer,
+	recording: Arc<AtomicBool>,
+	counters: Arc<Counters>,
 }
 
 impl AudioCallback for Capture {
 	type Channel = i16;
 
 	fn callback(&mut self, input: &mut [i16]) {
-		for chunk in input.chunks(self.size) {
-			self.done_sender.send(chunk.to_owned()).expect("could not send record buffer");
+		if self.recording.load(Ordering::Relaxed) && !self.record.push(input) {
+			self.counters.overruns.fetch_add(1, Ordering::Relaxed);
+		}
+		// nobody reads the analysis samples unless the meters are shown, just drop them then
+		self.analysis.push(input);
+		if !self.producer.push(input) {
+			self.counters.overruns.fetch_add(1, Ordering::Relaxed);
 		}
 	}
 }
 
+/// Plays the captured samples, keeping about `target` samples buffered: when the capture runs
+/// ahead the oldest samples are dropped, when it runs behind silence is played until the buffer
+/// is filled again.
 struct Playback {
-	done_receiver: mpsc::Receiver<Vec<i16>>,
+	consumer: Consumer,
+	target: usize,
+	max: usize,
+	filling: bool,
+	samples_per_ms: usize,
+	counters: Arc<Counters>,
+	fx: Chain,
 }
 
 impl AudioCallback for Playback {
 	type Channel = i16;
 
 	fn callback(&mut self, output: &mut [i16]) {
-		if let Ok(vec) = self.done_receiver.recv() {
-			output.copy_from_slice(&vec);
+		let available = self.consumer.available();
+		self.counters.latency.store((available / self.samples_per_ms) as u32, Ordering::Relaxed);
+		if available > self.max {
+			let excess = available - self.target;
+			self.consumer.skip(excess - excess % CHANNELS as usize);
+			self.counters.overruns.fetch_add(1, Ordering::Relaxed);
+		}
+		if self.filling {
+			if available < self.target {
+				output.fill(0);
+				return;
+			}
+			self.filling = false;
 		}
+		let n = self.consumer.pop(output);
+		if n < output.len() {
+			output[n..].fill(0);
+			self.filling = true;
+			self.counters.underruns.fetch_add(1, Ordering::Relaxed);
+		}
+		self.fx.process(&mut output[..n]);
 	}
 }
 
+/// Returns the names of the audio capture devices.
+pub fn capture_devices(audio: &AudioSubsystem) -> Vec<String> {
+	(0..audio.num_audio_capture_devices().unwrap_or(0))
+		.filter_map(|i| audio.audio_capture_device_name(i).ok())
+		.collect()
+}
+
+/// Returns the names of the audio playback devices.
+pub fn playback_devices(audio: &AudioSubsystem) -> Vec<String> {
+	(0..audio.num_audio_playback_devices().unwrap_or(0))
+		.filter_map(|i| audio.audio_playback_device_name(i).ok())
+		.collect()
+}
+
 pub struct Audio {
 	capture: AudioDevice<Capture>,
 	playback: AudioDevice<Playback>,
 	playing: bool,
 	name: String,
+	config: AudioConfig,
+	subsystem: AudioSubsystem,
+	record: Arc<Mutex<Consumer>>,
+	recording: Arc<AtomicBool>,
+	recorder: Option<WavRecorder>,
+	counters: Arc<Counters>,
+	analysis: Consumer,
+	fx: Arc<Params>,
 }
 
 impl Audio {
 	fn real_open(
-		audio: &sdl2::AudioSubsystem,
+		audio: &AudioSubsystem,
 		device_name: String,
-		samples: Option<u16>,
+		config: &AudioConfig,
+		fx: Arc<Params>,
 	) -> Result<Self, String> {
-		let spec = AudioSpecDesired { freq: Some(44100), channels: None, samples };
-		let (done_sender, done_receiver) = mpsc::channel();
-		let capture = audio.open_capture(Some(device_name.as_ref()), &spec, |spec| Capture {
-			done_sender,
-			size: samples.unwrap_or(spec.samples as u16) as usize * 2,
+		let spec = AudioSpecDesired {
+			freq: Some(config.rate),
+			channels: Some(CHANNELS),
+			samples: config.samples,
+		};
+		let counters = Arc::new(Counters::default());
+		let recording = Arc::new(AtomicBool::new(false));
+		let mut ring = None;
+		let capture = audio.open_capture(Some(device_name.as_ref()), &spec, |spec| {
+			// one second of audio is more than enough room for any sensible latency
+			let (producer, consumer) = ring::ring(spec.freq as usize * CHANNELS as usize);
+			let (analysis, analysis_consumer) =
+				ring::ring(spec.freq as usize * CHANNELS as usize / 4);
+			let (record, record_consumer) = ring::ring(spec.freq as usize * CHANNELS as usize);
+			let size = spec.samples as usize * CHANNELS as usize;
+			ring = Some((consumer, analysis_consumer, record_consumer, size));
+			Capture {
+				producer,
+				analysis,
+				record,
+				recording: recording.clone(),
+				counters: counters.clone(),
+			}
+		})?;
+		let (consumer, analysis, record, capture_size) = ring.ok_or("capture device not opened")?;
+		let playback = audio.open_playback(config.playback.as_deref(), &spec, |spec| {
+			let samples_per_ms = (spec.freq as usize * CHANNELS as usize / 1000).max(1);
+			let size = capture_size.max(spec.samples as usize * CHANNELS as usize);
+			// the buffer must hold at least one capture or playback callback worth of samples
+			let target = (config.latency as usize * samples_per_ms).max(size);
+			Playback {
+				consumer,
+				target,
+				max: target + size * 2,
+				filling: true,
+				samples_per_ms,
+				counters: counters.clone(),
+				fx: Chain::new(fx.clone(), spec.freq),
+			}
 		})?;
-		let playback = audio.open_playback(None, &spec, |_spec| Playback { done_receiver })?;
 
-		Ok(Self { playing: false, capture, playback, name: device_name })
+		Ok(Self {
+			playing: false,
+			capture,
+			playback,
+			name: device_name,
+			config: config.clone(),
+			subsystem: audio.clone(),
+			record: Arc::new(Mutex::new(record)),
+			recording,
+			recorder: None,
+			counters,
+			analysis,
+			fx,
+		})
 	}
 
-	pub fn open(
-		audio: &sdl2::AudioSubsystem,
-		name: Option<String>,
-		samples: Option<u16>,
+	/// Opens the configured capture device, or the first M8 found, and connects it to the
+	/// configured playback device (`None` being the default one).
+	pub fn open(audio: &AudioSubsystem, config: &AudioConfig) -> Result<Self, String> {
+		Self::open_with_fx(audio, config, Arc::default())
+	}
+
+	fn open_with_fx(
+		audio: &AudioSubsystem,
+		config: &AudioConfig,
+		fx: Arc<Params>,
 	) -> Result<Self, String> {
-		if let Some(device_name) = name {
-			Self::real_open(audio, device_name, samples)
+		if let Some(ref device_name) = config.capture {
+			Self::real_open(audio, device_name.clone(), config, fx)
 		} else {
-			for i in 0..audio.num_audio_capture_devices().unwrap_or(0) {
-				if let Ok(device_name) = audio.audio_capture_device_name(i) {
-					if device_name.starts_with("M8 Analog Stereo") {
-						return Self::real_open(audio, device_name, samples);
-					}
+			for device_name in capture_devices(audio) {
+				if device_name.starts_with("M8 Analog Stereo") {
+					return Self::real_open(audio, device_name, config, fx);
 				}
 			}
 			Err("No M8 audio device found".to_string())
 		}
 	}
 
-	pub fn reopen(
-		&mut self,
-		audio: &sdl2::AudioSubsystem,
-		name: String,
-		samples: Option<u16>,
-	) -> Result<(), String> {
-		*self = Self::real_open(audio, name, samples)?;
-		Ok(())
+	/// Reopens the devices if the config changed, keeping the playing state and the effects.
+	/// The old devices are closed first, as the new ones may be the same; a recording stops
+	/// with them.
+	pub fn reopen(mut self, config: &AudioConfig) -> Result<Self, String> {
+		if *config == self.config {
+			return Ok(self);
+		}
+		let (subsystem, fx, playing) = (self.subsystem.clone(), self.fx.clone(), self.playing);
+		self.pause();
+		drop(self);
+		let mut audio = Self::open_with_fx(&subsystem, config, fx)?;
+		if playing {
+			audio.resume();
+		}
+		Ok(audio)
+	}
+
+	pub fn config(&self) -> &AudioConfig {
+		&self.config
 	}
 
 	pub fn toggle(&mut self) {
@@ -107,4 +263,59 @@ impl Audio {
 	pub fn name(&self) -> String {
 		self.name.clone()
 	}
+
+	/// Moves the samples captured since the last call to `out` and returns the sample rate.
+	pub fn samples(&mut self, out: &mut Vec<i16>) -> i32 {
+		out.resize(self.analysis.available(), 0);
+		let n = self.analysis.pop(out);
+		out.truncate(n);
+		self.capture.spec().freq
+	}
+
+	pub fn set_fx(&mut self, config: &AudioFxConfig) {
+		self.fx.set(config);
+	}
+
+	pub fn stats(&self) -> Stats {
+		Stats {
+			underruns: self.counters.underruns.load(Ordering::Relaxed),
+			overruns: self.counters.overruns.load(Ordering::Relaxed),
+			latency: self.counters.latency.load(Ordering::Relaxed),
+		}
+	}
+
+	pub fn playback_name(&self) -> Option<String> {
+		self.config.playback.clone()
+	}
+
+	/// Starts writing the captured audio to a WAV file, monitoring goes on meanwhile.
+	pub fn start_recording(&mut self, file: &Path) -> Result<(), String> {
+		self.stop_recording();
+		let spec = self.capture.spec();
+		let recorder = WavRecorder::create(
+			file,
+			spec.freq as u32,
+			spec.channels as u16,
+			self.record.clone(),
+			self.recording.clone(),
+		)?;
+		self.recorder.replace(recorder);
+		Ok(())
+	}
+
+	pub fn stop_recording(&mut self) -> Option<Result<PathBuf, String>> {
+		self.recorder.take().map(|recorder| recorder.finish())
+	}
+
+	pub fn recording(&self) -> bool {
+		self.recorder.is_some()
+	}
+}
+
+impl Drop for Audio {
+	fn drop(&mut self) {
+		if let Some(Err(e)) = self.stop_recording() {
+			eprintln!("audio recording failed: {}", e);
+		}
+	}
 }
@@ -0,0 +1,344 @@
+use std::{
+	collections::HashMap,
+	fs::{self, File},
+	io::{BufWriter, Write},
+	path::{Path, PathBuf},
+	sync::mpsc,
+	thread,
+	time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use crate::config::VideoFormat;
+
+/// GIF delays are expressed in hundredths of a second, and most viewers do not honour delays
+/// below 2/100s, so frames are not captured more often than this.
+pub const FRAME_INTERVAL: Duration = Duration::from_millis(20);
+/// Frames waiting to be encoded, the next ones are dropped until the encoder catches up.
+const FRAME_QUEUE: usize = 8;
+
+/// Converts ARGB8888 pixels, as returned by `Canvas::read_pixels`, to RGB and scales them by
+/// `zoom` using nearest neighbour.
+pub fn argb_to_rgb(pixels: &[u8], width: u32, height: u32, zoom: u32) -> Vec<u8> {
+	let zoom = zoom.max(1) as usize;
+	let (width, height) = (width as usize, height as usize);
+	let mut rgb = Vec::with_capacity(width * height * zoom * zoom * 3);
+	for row in pixels.chunks_exact(width * 4).take(height) {
+		let mut line = Vec::with_capacity(width * zoom * 3);
+		for p in row.chunks_exact(4) {
+			let argb = u32::from_le_bytes([p[0], p[1], p[2], p[3]]);
+			let (r, g, b) = ((argb >> 16) as u8, (argb >> 8) as u8, argb as u8);
+			for _ in 0..zoom {
+				line.extend_from_slice(&[r, g, b]);
+			}
+		}
+		for _ in 0..zoom {
+			rgb.extend_from_slice(&line);
+		}
+	}
+	rgb
+}
+
+pub fn write_png(file: &Path, rgb: &[u8], width: u32, height: u32) -> Result<(), String> {
+	let writer = BufWriter::new(File::create(file).map_err(|e| e.to_string())?);
+	let mut encoder = png::Encoder::new(writer, width, height);
+	encoder.set_color(png::ColorType::Rgb);
+	encoder.set_depth(png::BitDepth::Eight);
+	let mut writer = encoder.write_header().map_err(|e| e.to_string())?;
+	writer.write_image_data(rgb).map_err(|e| e.to_string())
+}
+
+/// Returns the path of a new file in `directory`, named after the current date and time.
+pub fn new_file(directory: &str, extension: &str) -> Result<PathBuf, String> {
+	fs::create_dir_all(directory).map_err(|e| e.to_string())?;
+	Ok(Path::new(directory).join(format!("rm8-{}.{}", timestamp(), extension)))
+}
+
+/// Saves a screenshot in `directory`, named after the current date and time.
+pub fn screenshot(
+	directory: &str,
+	pixels: &[u8],
+	width: u32,
+	height: u32,
+	zoom: u32,
+) -> Result<PathBuf, String> {
+	let file = new_file(directory, "png")?;
+	let rgb = argb_to_rgb(pixels, width, height, zoom);
+	write_png(&file, &rgb, width * zoom.max(1), height * zoom.max(1))?;
+	Ok(file)
+}
+
+/// Returns the current UTC date and time as `YYYYMMDD-HHMMSS-mmm`.
+pub fn timestamp() -> String {
+	let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+	let secs = now.as_secs();
+	let (days, secs) = ((secs / 86400) as i64, secs % 86400);
+	// http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+	let z = days + 719468;
+	let era = z.div_euclid(146097);
+	let doe = z - era * 146097;
+	let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+	let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+	let mp = (5 * doy + 2) / 153;
+	let day = doy - (153 * mp + 2) / 5 + 1;
+	let month = if mp < 10 { mp + 3 } else { mp - 9 };
+	let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+	format!(
+		"{:04}{:02}{:02}-{:02}{:02}{:02}-{:03}",
+		year,
+		month,
+		day,
+		secs / 3600,
+		secs / 60 % 60,
+		secs % 60,
+		now.subsec_millis()
+	)
+}
+
+struct VideoFrame {
+	time: Duration,
+	pixels: Vec<u8>,
+}
+
+/// Records the frames of the display, either as an animated GIF or as a numbered sequence of PNG
+/// files with a `timing.csv` sidecar giving the time of each frame in milliseconds.
+///
+/// Frames are encoded on a separate thread, so that recording does not slow down the display;
+/// they are dropped while the encoder lags behind.
+pub struct VideoRecorder {
+	sender: Option<mpsc::SyncSender<VideoFrame>>,
+	thread: Option<thread::JoinHandle<Result<(), String>>>,
+	start: Instant,
+	last: Option<Instant>,
+	path: PathBuf,
+	size: (u32, u32),
+	dropped: usize,
+}
+
+impl VideoRecorder {
+	pub fn start(
+		directory: &str,
+		format: VideoFormat,
+		width: u32,
+		height: u32,
+		zoom: u32,
+	) -> Result<Self, String> {
+		fs::create_dir_all(directory).map_err(|e| e.to_string())?;
+		let name = format!("rm8-{}", timestamp());
+		let (sender, receiver) = mpsc::sync_channel(FRAME_QUEUE);
+		let (path, thread) = match format {
+			VideoFormat::Gif => {
+				let size = gif_size(width, height, zoom)?;
+				let path = Path::new(directory).join(format!("{}.gif", name));
+				let file = File::create(&path).map_err(|e| e.to_string())?;
+				let thread =
+					thread::spawn(move || write_gif(file, receiver, width, height, zoom, size));
+				(path, thread)
+			}
+			VideoFormat::Png => {
+				let path = Path::new(directory).join(name);
+				fs::create_dir_all(&path).map_err(|e| e.to_string())?;
+				let dir = path.clone();
+				let thread =
+					thread::spawn(move || write_png_sequence(&dir, receiver, width, height, zoom));
+				(path, thread)
+			}
+		};
+		Ok(Self {
+			sender: Some(sender),
+			thread: Some(thread),
+			start: Instant::now(),
+			last: None,
+			path,
+			size: (width, height),
+			dropped: 0,
+		})
+	}
+
+	/// Returns true if enough time elapsed since the last frame to capture a new one.
+	pub fn wants_frame(&self) -> bool {
+		self.last.map(|last| last.elapsed() >= FRAME_INTERVAL).unwrap_or(true)
+	}
+
+	/// Queues a frame of ARGB8888 pixels, as returned by `Canvas::read_pixels`, or drops it if
+	/// the queue is full.
+	pub fn push(&mut self, pixels: Vec<u8>) -> Result<(), String> {
+		let now = Instant::now();
+		self.last = Some(now);
+		let frame = VideoFrame { time: now - self.start, pixels };
+		match self.sender {
+			Some(ref sender) => match sender.try_send(frame) {
+				Ok(()) => Ok(()),
+				Err(mpsc::TrySendError::Full(_)) => {
+					self.dropped += 1;
+					Ok(())
+				}
+				Err(mpsc::TrySendError::Disconnected(_)) => {
+					Err("video encoder stopped".to_string())
+				}
+			},
+			None => Ok(()),
+		}
+	}
+
+	pub fn path(&self) -> &Path {
+		&self.path
+	}
+
+	/// Returns the size of the frames, before zooming.
+	pub fn size(&self) -> (u32, u32) {
+		self.size
+	}
+
+	/// Returns the number of frames dropped because the encoder was lagging behind.
+	pub fn dropped(&self) -> usize {
+		self.dropped
+	}
+
+	/// Stops the recording and returns at once: the pending frames are written on another
+	/// thread, which calls `done` with the path of the video once it is complete.
+	pub fn stop<F>(mut self, done: F) -> thread::JoinHandle<()>
+	where
+		F: FnOnce(Result<PathBuf, String>) + Send + 'static,
+	{
+		self.sender.take();
+		let thread = self.thread.take();
+		let path = self.path.clone();
+		thread::spawn(move || {
+			let result = match thread {
+				Some(thread) => thread.join().map_err(|_| "video encoder panicked".to_string()),
+				None => Ok(Ok(())),
+			};
+			done(result.and_then(|r| r).map(|_| path))
+		})
+	}
+
+	fn finish(&mut self) -> Result<(), String> {
+		self.sender.take();
+		match self.thread.take() {
+			Some(thread) => thread.join().map_err(|_| "video encoder panicked".to_string())?,
+			None => Ok(()),
+		}
+	}
+}
+
+impl Drop for VideoRecorder {
+	fn drop(&mut self) {
+		let _ = self.finish();
+	}
+}
+
+fn write_gif(
+	file: File,
+	receiver: mpsc::Receiver<VideoFrame>,
+	width: u32,
+	height: u32,
+	zoom: u32,
+	(w, h): (u16, u16),
+) -> Result<(), String> {
+	let zoom = zoom.max(1);
+	let mut encoder =
+		gif::Encoder::new(BufWriter::new(file), w, h, &[]).map_err(|e| e.to_string())?;
+	encoder.set_repeat(gif::Repeat::Infinite).map_err(|e| e.to_string())?;
+	// a frame is only written once the next one arrives, as its delay is not known before
+	let mut pending: Option<(Duration, Vec<u8>)> = None;
+	let mut flush = |pending: &Option<(Duration, Vec<u8>)>, end: Duration| {
+		if let Some((time, ref rgb)) = pending {
+			let delay = ((end - *time).as_millis() / 10).clamp(2, u16::MAX as u128) as u16;
+			let mut frame = gif_frame(w, h, rgb);
+			frame.delay = delay;
+			encoder.write_frame(&frame).map_err(|e| e.to_string())?;
+		}
+		Ok::<(), String>(())
+	};
+	let mut end = Duration::ZERO;
+	for frame in receiver {
+		let rgb = argb_to_rgb(&frame.pixels, width, height, zoom);
+		end = frame.time;
+		if let Some((_, ref prev)) = pending {
+			if *prev == rgb {
+				continue;
+			}
+		}
+		flush(&pending, frame.time)?;
+		pending = Some((frame.time, rgb));
+	}
+	flush(&pending, end + FRAME_INTERVAL)
+}
+
+/// Builds an indexed frame, using an exact palette when the frame has at most 256 colors (which
+/// is usually the case for the M8) and quantizing it otherwise.
+/// Size of the GIF frames, which can't be larger than 65535 pixels.
+fn gif_size(width: u32, height: u32, zoom: u32) -> Result<(u16, u16), String> {
+	let zoom = zoom.max(1);
+	let size = |len: u32| {
+		u16::try_from(len.saturating_mul(zoom))
+			.map_err(|_| format!("the screen zoomed {} times is too large for a GIF", zoom))
+	};
+	Ok((size(width)?, size(height)?))
+}
+
+fn gif_frame(width: u16, height: u16, rgb: &[u8]) -> gif::Frame<'static> {
+	let mut palette: HashMap<[u8; 3], u8> = HashMap::new();
+	let mut indices = Vec::with_capacity(rgb.len() / 3);
+	for p in rgb.chunks_exact(3) {
+		let color = [p[0], p[1], p[2]];
+		let len = palette.len();
+		match palette.get(&color) {
+			Some(&i) => indices.push(i),
+			None if len < 256 => {
+				palette.insert(color, len as u8);
+				indices.push(len as u8);
+			}
+			None => {
+				let mut rgba: Vec<u8> =
+					rgb.chunks_exact(3).flat_map(|p| [p[0], p[1], p[2], 0xff]).collect();
+				return gif::Frame::from_rgba_speed(width, height, &mut rgba, 10);
+			}
+		}
+	}
+	let mut colors = vec![0; palette.len() * 3];
+	for (color, i) in palette {
+		colors[i as usize * 3..i as usize * 3 + 3].copy_from_slice(&color);
+	}
+	gif::Frame::from_palette_pixels(width, height, indices, colors, None)
+}
+
+fn write_png_sequence(
+	directory: &Path,
+	receiver: mpsc::Receiver<VideoFrame>,
+	width: u32,
+	height: u32,
+	zoom: u32,
+) -> Result<(), String> {
+	let zoom = zoom.max(1);
+	let timing = File::create(directory.join("timing.csv")).map_err(|e| e.to_string())?;
+	let mut timing = BufWriter::new(timing);
+	writeln!(timing, "file,ms").map_err(|e| e.to_string())?;
+	let mut prev = vec![];
+	let mut count = 0;
+	for frame in receiver {
+		let rgb = argb_to_rgb(&frame.pixels, width, height, zoom);
+		if rgb == prev {
+			continue;
+		}
+		let name = format!("frame-{:05}.png", count);
+		write_png(&directory.join(&name), &rgb, width * zoom, height * zoom)?;
+		writeln!(timing, "{},{}", name, frame.time.as_millis()).map_err(|e| e.to_string())?;
+		prev = rgb;
+		count += 1;
+	}
+	timing.flush().map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn rejects_the_gifs_too_large() {
+		assert_eq!(gif_size(320, 240, 0), Ok((320, 240)));
+		assert_eq!(gif_size(320, 240, 4), Ok((1280, 960)));
+		assert!(gif_size(320, 240, 205).is_err());
+		assert!(gif_size(u32::MAX, 240, 2).is_err());
+	}
+}
@@ -2,28 +2,57 @@ use std::env;
 
 use crate::config::Config;
 use crate::m8::M8;
+use crate::midi;
 
 const USAGE: &str = "Usage rm8 [options]
 Available options:
 	-help		 Display this help screen
 	-version	 Display the version of the program
-	-list		 List available M8 devices
+	-list		 List available M8 and MIDI devices
 	-noaudio	 Disable audio loopback mode
 	-dev DEVICE	 Connect to the the given M8 device
-	-cap DEVICE  Connect the given capture device to the default playback device
+				 (or tcp:HOST:PORT, pipe:INPUT:OUTPUT), repeat it
+				 to connect to several M8s at once
+	-all		 Connect to all the M8 devices found
+	-cap DEVICE  Connect the given capture device to the playback device
 	-smp SAMPLES Use the specified number of samples for audio processing
 	-wc			 Write the default configuration to the standard output
 	-wc FILE	 Write the default configuration to the given file
-	-rc FILE	 Read the configuration from the given file";
+	-rc FILE	 Read the configuration from the given file
+	-record FILE Record the display stream of the M8 to the given file
+	-replay FILE Replay a display stream recorded with -record
+	-recaudio FILE Record the audio loopback to the given WAV file
+	-serve PORT	 Share the display and the input of the M8 over TCP
+				 (PORT for localhost only, or ADDRESS:PORT)
+	-web PORT	 Serve a web page showing the M8 display over HTTP
+				 (PORT for localhost only, or ADDRESS:PORT)
+	-webinput	 Let the web page send keys and notes to the M8
+	-connect HOST:PORT Connect to an M8 shared by another rm8 with -serve
+	-emulator	 Connect to a built-in M8 emulator instead of a device
+	-midi PORT	 Play the notes of the given MIDI port on the M8
+				 (a sequencer port, virtual for a port of rm8,
+				 or a raw device like /dev/snd/midiC1D0)";
 
-pub fn handle_command_line(
-	config: &mut Config,
-	config_file: &mut Option<String>,
-	device: &mut Option<String>,
-	capture: &mut Option<String>,
-	samples: &mut Option<u16>,
-	noaudio: &mut bool,
-) -> Result<bool, String> {
+#[derive(Default)]
+pub struct Options {
+	pub config_file: Option<String>,
+	pub devices: Vec<String>,
+	pub all: bool,
+	pub capture: Option<String>,
+	pub samples: Option<u16>,
+	pub noaudio: bool,
+	pub record: Option<String>,
+	pub replay: Option<String>,
+	pub record_audio: Option<String>,
+	pub emulator: bool,
+	pub serve: Option<String>,
+	pub connect: Option<String>,
+	pub web: Option<String>,
+	pub web_input: bool,
+	pub midi: Option<String>,
+}
+
+pub fn handle_command_line(config: &mut Config, options: &mut Options) -> Result<bool, String> {
 	let mut args = env::args().skip(1);
 	loop {
 		match args.next().as_deref() {
@@ -41,6 +70,13 @@ pub fn handle_command_line(
 				for port in ports {
 					println!("\t- {}", port);
 				}
+				let devices = midi::list_devices();
+				if !devices.is_empty() {
+					println!("MIDI ports found:");
+					for device in devices {
+						println!("\t- {}", device);
+					}
+				}
 				return Ok(false);
 			}
 			Some("-wc") => match args.next() {
@@ -48,7 +84,7 @@ pub fn handle_command_line(
 					if let Err(e) = config.write(&file) {
 						return Err(format!("Error: writing config to file {} ({})", &file, e));
 					}
-					config_file.replace(file);
+					options.config_file.replace(file);
 					return Ok(false);
 				}
 				None => match config.dump() {
@@ -64,22 +100,28 @@ pub fn handle_command_line(
 					if let Err(e) = config.read(&file) {
 						return Err(format!("Error: loading config file `{}` ({})", file, e));
 					}
-					config_file.replace(file);
+					options.config_file.replace(file);
 				}
 				None => return Err("Error: missing config file argument".to_string()),
 			},
+			Some("-emulator") => {
+				options.emulator = true;
+			}
 			Some("-noaudio") => {
-				*noaudio = true;
+				options.noaudio = true;
 			}
 			Some("-dev") => match args.next() {
 				Some(dev) => {
-					device.replace(dev);
+					options.devices.push(dev);
 				}
 				None => return Err("Error: missing device argument".to_string()),
 			},
+			Some("-all") => {
+				options.all = true;
+			}
 			Some("-cap") => match args.next() {
 				Some(cap) => {
-					capture.replace(cap);
+					options.capture.replace(cap);
 				}
 				None => return Err("Error: missing capture argument".to_string()),
 			},
@@ -87,10 +129,55 @@ pub fn handle_command_line(
 				Some(smp) => {
 					let smp =
 						smp.parse().map_err(|_| "Error: invalid samples argument".to_string())?;
-					samples.replace(smp);
+					options.samples.replace(smp);
 				}
 				None => return Err("Error: missing samples argument".to_string()),
 			},
+			Some("-record") => match args.next() {
+				Some(file) => {
+					options.record.replace(file);
+				}
+				None => return Err("Error: missing record file argument".to_string()),
+			},
+			Some("-replay") => match args.next() {
+				Some(file) => {
+					options.replay.replace(file);
+				}
+				None => return Err("Error: missing replay file argument".to_string()),
+			},
+			Some("-recaudio") => match args.next() {
+				Some(file) => {
+					options.record_audio.replace(file);
+				}
+				None => return Err("Error: missing audio record file argument".to_string()),
+			},
+			Some("-serve") => match args.next() {
+				Some(addr) => {
+					options.serve.replace(addr);
+				}
+				None => return Err("Error: missing serve port argument".to_string()),
+			},
+			Some("-web") => match args.next() {
+				Some(addr) => {
+					options.web.replace(addr);
+				}
+				None => return Err("Error: missing web port argument".to_string()),
+			},
+			Some("-webinput") => {
+				options.web_input = true;
+			}
+			Some("-connect") => match args.next() {
+				Some(addr) => {
+					options.connect.replace(addr);
+				}
+				None => return Err("Error: missing connect address argument".to_string()),
+			},
+			Some("-midi") => match args.next() {
+				Some(device) => {
+					options.midi.replace(device);
+				}
+				None => return Err("Error: missing MIDI device argument".to_string()),
+			},
 			Some(arg) => return Err(format!("Error: unknown argument: {}", arg)),
 			None => break,
 		}
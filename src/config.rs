@@ -1,12 +1,16 @@
-use sdl2::keyboard::Keycode as SdlKeycode;
+use sdl2::keyboard::{Keycode as SdlKeycode, Mod};
 use std::collections::HashMap;
 
 pub use crate::config_command::Command;
 pub use crate::config_font::Font;
 use crate::config_joystick::{joysticks_empty, serialize_joysticks};
 pub use crate::config_joystick::{Axis, HatConfig, JoystickConfig, DEFAULT_SENSIBILITY};
-pub use crate::config_keycode::Keycode;
+pub use crate::config_keycode::{Binding, Bindings, Keycode};
 pub use crate::config_rgb::Rgb;
+use crate::{
+	m8::{SCREEN_HEIGHT, SCREEN_WIDTH},
+	midi::{self, Control},
+};
 
 #[derive(Debug, Clone, Copy, Serialize, Deserialize)]
 #[serde(default)]
@@ -40,12 +44,67 @@ impl Default for ThemeConfig {
 	}
 }
 
+/// How the screens are scaled to the window.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Scaling {
+	/// Whole multiples of the screen size only, with borders.
+	Integer,
+	/// As large as possible keeping the aspect ratio, with borders.
+	#[default]
+	Fit,
+	/// The whole window, whatever the aspect ratio.
+	Stretch,
+}
+
+impl Scaling {
+	pub const ALL: [Self; 3] = [Self::Integer, Self::Fit, Self::Stretch];
+	pub const NAMES: &'static [&'static str] = &["INTEGER", "FIT", "STRETCH"];
+}
+
+/// How the pixels are interpolated when scaling the screens.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Filter {
+	#[default]
+	Nearest,
+	Linear,
+}
+
+impl Filter {
+	pub const ALL: [Self; 2] = [Self::Nearest, Self::Linear];
+	pub const NAMES: &'static [&'static str] = &["NEAREST", "LINEAR"];
+
+	/// Returns the value of the SDL scale quality hint.
+	pub fn hint(self) -> &'static str {
+		match self {
+			Self::Nearest => "nearest",
+			Self::Linear => "linear",
+		}
+	}
+}
+
+/// Lines drawn over the scaled screens, in the manner of a CRT or an LCD.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Effect {
+	#[default]
+	None,
+	Scanlines,
+	Grid,
+}
+
+impl Effect {
+	pub const ALL: [Self; 3] = [Self::None, Self::Scanlines, Self::Grid];
+	pub const NAMES: &'static [&'static str] = &["NONE", "SCANLINES", "GRID"];
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(default)]
 pub struct AppConfig {
 	pub fullscreen: bool,
 	pub font: Font,
 	pub zoom: u32,
+	pub scaling: Scaling,
+	pub filter: Filter,
+	pub effect: Effect,
 	pub key_sensibility: u64,
 	pub fps: usize,
 	pub show_fps: bool,
@@ -58,6 +117,9 @@ impl Default for AppConfig {
 			fullscreen: false,
 			font: Font::Uppercase,
 			zoom: 4,
+			scaling: Scaling::default(),
+			filter: Filter::default(),
+			effect: Effect::default(),
 			key_sensibility: 60,
 			fps: 60,
 			show_fps: false,
@@ -69,14 +131,14 @@ impl Default for AppConfig {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(default)]
 pub struct M8KeyboardConfig {
-	pub up: Keycode,
-	pub down: Keycode,
-	pub left: Keycode,
-	pub right: Keycode,
-	pub edit: Keycode,
-	pub option: Keycode,
-	pub shift: Keycode,
-	pub play: Keycode,
+	pub up: Bindings,
+	pub down: Bindings,
+	pub left: Bindings,
+	pub right: Bindings,
+	pub edit: Bindings,
+	pub option: Bindings,
+	pub shift: Bindings,
+	pub play: Bindings,
 }
 
 impl Default for M8KeyboardConfig {
@@ -97,11 +159,28 @@ impl Default for M8KeyboardConfig {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(default)]
 pub struct RM8KeyboardConfig {
-	pub keyjazz: Keycode,
-	pub velocity_minus: Keycode,
-	pub velocity_plus: Keycode,
-	pub octave_minus: Keycode,
-	pub octave_plus: Keycode,
+	pub keyjazz: Bindings,
+	pub velocity_minus: Bindings,
+	pub velocity_plus: Bindings,
+	pub octave_minus: Bindings,
+	pub octave_plus: Bindings,
+	pub screenshot: Bindings,
+	pub record: Bindings,
+	pub record_audio: Bindings,
+	pub meters: Bindings,
+	pub gain_minus: Bindings,
+	pub gain_plus: Bindings,
+	pub mute: Bindings,
+	pub layout: Bindings,
+	pub focus: Bindings,
+	pub diagnostics: Bindings,
+	pub macro_record: Bindings,
+	pub macro_play: Bindings,
+	pub config: Bindings,
+	pub escape: Bindings,
+	pub fullscreen: Bindings,
+	pub reset: Bindings,
+	pub reset_full: Bindings,
 }
 
 impl Default for RM8KeyboardConfig {
@@ -112,6 +191,220 @@ impl Default for RM8KeyboardConfig {
 			velocity_plus: SdlKeycode::Equals.into(),
 			octave_minus: SdlKeycode::LeftBracket.into(),
 			octave_plus: SdlKeycode::RightBracket.into(),
+			screenshot: SdlKeycode::F12.into(),
+			record: SdlKeycode::F11.into(),
+			record_audio: SdlKeycode::F10.into(),
+			meters: SdlKeycode::F9.into(),
+			gain_minus: SdlKeycode::F6.into(),
+			gain_plus: SdlKeycode::F7.into(),
+			mute: SdlKeycode::F8.into(),
+			layout: SdlKeycode::F4.into(),
+			focus: SdlKeycode::F5.into(),
+			diagnostics: SdlKeycode::F3.into(),
+			macro_record: SdlKeycode::F2.into(),
+			macro_play: SdlKeycode::F1.into(),
+			config: Binding::new(SdlKeycode::C, Mod::LALTMOD).into(),
+			escape: SdlKeycode::Escape.into(),
+			fullscreen: Binding::new(SdlKeycode::Return, Mod::LALTMOD).into(),
+			reset: Binding::new(SdlKeycode::R, Mod::LALTMOD).into(),
+			reset_full: Binding::new(SdlKeycode::R, Mod::LALTMOD | Mod::LSHIFTMOD).into(),
+		}
+	}
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AudioConfig {
+	pub enabled: bool,
+	pub capture: Option<String>,
+	pub playback: Option<String>,
+	pub samples: Option<u16>,
+	pub rate: i32,
+	pub latency: u32,
+	pub show_stats: bool,
+}
+
+impl Default for AudioConfig {
+	fn default() -> Self {
+		Self {
+			enabled: true,
+			capture: None,
+			playback: None,
+			samples: None,
+			rate: 44100,
+			latency: 50,
+			show_stats: false,
+		}
+	}
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AudioFxConfig {
+	pub gain: i32,
+	pub mute: bool,
+	pub limiter: bool,
+	pub threshold: i32,
+	pub low: i32,
+	pub mid: i32,
+	pub high: i32,
+}
+
+impl Default for AudioFxConfig {
+	fn default() -> Self {
+		Self { gain: 0, mute: false, limiter: false, threshold: -1, low: 0, mid: 0, high: 0 }
+	}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum VideoFormat {
+	Gif,
+	Png,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CaptureConfig {
+	pub directory: String,
+	pub zoom: bool,
+	pub video: VideoFormat,
+	pub audio: bool,
+}
+
+impl Default for CaptureConfig {
+	fn default() -> Self {
+		Self { directory: ".".to_string(), zoom: false, video: VideoFormat::Gif, audio: false }
+	}
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MidiConfig {
+	/// MIDI port to read the notes from: a sequencer port, `virtual` for a port of rm8, or a raw
+	/// device like `/dev/snd/midiC1D0`.
+	pub device: Option<String>,
+	/// Channel to listen to, from 1 to 16, or all of them.
+	pub channel: Option<u8>,
+	/// Plays the notes from the octave of rm8, the middle C being its first note, rather than as
+	/// they come.
+	pub octave: bool,
+	/// Sends the velocity of the notes rather than the one of rm8.
+	pub velocity: bool,
+	/// Notes which run a command rather than being played.
+	#[serde(skip_serializing_if = "HashMap::is_empty")]
+	pub notes: HashMap<u8, Command>,
+	/// Controllers which run a command, pressed from the value 64.
+	#[serde(skip_serializing_if = "HashMap::is_empty")]
+	pub controls: HashMap<u8, Command>,
+}
+
+impl Default for MidiConfig {
+	fn default() -> Self {
+		Self {
+			device: None,
+			channel: None,
+			octave: false,
+			velocity: true,
+			notes: HashMap::new(),
+			controls: HashMap::new(),
+		}
+	}
+}
+
+impl MidiConfig {
+	/// Returns true if the messages of the channel, from 0 to 15, are to be used.
+	pub fn listens(&self, channel: u8) -> bool {
+		self.channel.is_none_or(|c| c == channel + 1)
+	}
+
+	/// Returns the note to play on the M8 for a MIDI note.
+	pub fn note(&self, note: u8, octave: u8) -> u8 {
+		if self.octave {
+			(note as i16 - 60 + octave as i16 * 12).clamp(0, 127) as u8
+		} else {
+			note
+		}
+	}
+
+	/// Returns the note, else the controller, which runs the command.
+	pub fn control(&self, cmd: Command) -> Option<Control> {
+		let find = |map: &HashMap<u8, Command>| {
+			map.iter().filter(|&(_, c)| *c == cmd).map(|(&n, _)| n).min()
+		};
+		find(&self.notes)
+			.map(Control::Note)
+			.or_else(|| find(&self.controls).map(Control::Controller))
+	}
+
+	/// Returns the command of a message and whether it is released, controllers being pressed
+	/// from the value 64.
+	pub fn command(&self, message: &midi::Message) -> Option<(Command, bool)> {
+		match *message {
+			midi::Message::NoteOn { note, .. } => self.notes.get(&note).map(|&cmd| (cmd, false)),
+			midi::Message::NoteOff { note, .. } => self.notes.get(&note).map(|&cmd| (cmd, true)),
+			midi::Message::Control { controller, value, .. } => {
+				self.controls.get(&controller).map(|&cmd| (cmd, value < 64))
+			}
+		}
+	}
+}
+
+/// A step of a macro, written as a number for the M8 keys or as the name of a command.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum MacroStep {
+	/// M8 keys pressed together, as the bits of `m8.keys`.
+	Keys(u8),
+	Command(Command),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MacroConfig {
+	pub name: String,
+	#[serde(skip_serializing_if = "Bindings::is_empty")]
+	pub keys: Bindings,
+	/// Time each step is held and then released, in milliseconds.
+	pub delay: u64,
+	pub steps: Vec<MacroStep>,
+}
+
+impl Default for MacroConfig {
+	fn default() -> Self {
+		Self { name: String::new(), keys: Bindings::default(), delay: 60, steps: Vec::new() }
+	}
+}
+
+/// Size of the screens, when it is not the one of the M8 model.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ScreenConfig {
+	pub width: u32,
+	pub height: u32,
+}
+
+impl ScreenConfig {
+	/// Returns the size grown to the one of the smallest M8, the menus and the overlays do not
+	/// fit in less.
+	pub fn clamped(self) -> Self {
+		Self { width: self.width.max(SCREEN_WIDTH), height: self.height.max(SCREEN_HEIGHT) }
+	}
+}
+
+/// How the screens are arranged in the window when several M8s are connected.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Layout {
+	#[default]
+	SideBySide,
+	Tiled,
+	Single,
+}
+
+impl Layout {
+	pub fn next(self) -> Self {
+		match self {
+			Self::SideBySide => Self::Tiled,
+			Self::Tiled => Self::Single,
+			Self::Single => Self::SideBySide,
 		}
 	}
 }
@@ -122,12 +415,26 @@ pub struct Config {
 	pub theme: ThemeConfig,
 	pub m8: M8KeyboardConfig,
 	pub rm8: RM8KeyboardConfig,
+	#[serde(default)]
+	pub audio: AudioConfig,
+	#[serde(default)]
+	pub audio_fx: AudioFxConfig,
+	#[serde(default)]
+	pub capture: CaptureConfig,
+	#[serde(default)]
+	pub layout: Layout,
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub screen: Option<ScreenConfig>,
 	#[serde(
 		default,
 		skip_serializing_if = "joysticks_empty",
 		serialize_with = "serialize_joysticks"
 	)]
 	pub joysticks: HashMap<String, JoystickConfig>,
+	#[serde(default, skip_serializing_if = "Vec::is_empty")]
+	pub macros: Vec<MacroConfig>,
+	#[serde(default)]
+	pub midi: MidiConfig,
 	pub keyjazz: HashMap<Keycode, u8>,
 	#[serde(skip)]
 	pub overlap: bool,
@@ -140,6 +447,13 @@ impl Default for Config {
 			theme: ThemeConfig::default(),
 			m8: M8KeyboardConfig::default(),
 			rm8: RM8KeyboardConfig::default(),
+			audio: AudioConfig::default(),
+			audio_fx: AudioFxConfig::default(),
+			capture: CaptureConfig::default(),
+			layout: Layout::default(),
+			screen: None,
+			macros: Vec::new(),
+			midi: MidiConfig::default(),
 			keyjazz: HashMap::from([
 				(Keycode(SdlKeycode::Z), 0),
 				(Keycode(SdlKeycode::S), 1),
@@ -187,6 +501,7 @@ impl Config {
 		let content = std::fs::read_to_string(file.as_ref()).map_err(|e| e.to_string())?;
 		let config: Self = serde_json::from_str(&content).map_err(|e| e.to_string())?;
 		*self = config;
+		self.check_screen();
 		self.check_overlap();
 		Ok(())
 	}
@@ -200,19 +515,149 @@ impl Config {
 		serde_json::to_string_pretty(self).map_err(|e| e.to_string())
 	}
 
+	/// Returns the bindings of the keyboard with the command they trigger: every command but
+	/// `None` can be bound to keys.
+	/// Returns the keys bound to each command, in the order of `Command::ALL`.
+	pub fn key_bindings(&self) -> Vec<(&Bindings, Command)> {
+		Command::ALL.iter().filter_map(|(cmd, _)| Some((self.binding(*cmd)?, *cmd))).collect()
+	}
+
+	pub fn binding(&self, cmd: Command) -> Option<&Bindings> {
+		Some(match cmd {
+			Command::None => return None,
+			Command::Up => &self.m8.up,
+			Command::Down => &self.m8.down,
+			Command::Left => &self.m8.left,
+			Command::Right => &self.m8.right,
+			Command::Edit => &self.m8.edit,
+			Command::r#Option => &self.m8.option,
+			Command::Shift => &self.m8.shift,
+			Command::Play => &self.m8.play,
+			Command::Keyjazz => &self.rm8.keyjazz,
+			Command::VelocityMinus => &self.rm8.velocity_minus,
+			Command::VelocityPlus => &self.rm8.velocity_plus,
+			Command::OctaveMinus => &self.rm8.octave_minus,
+			Command::OctavePlus => &self.rm8.octave_plus,
+			Command::Screenshot => &self.rm8.screenshot,
+			Command::Record => &self.rm8.record,
+			Command::RecordAudio => &self.rm8.record_audio,
+			Command::Meters => &self.rm8.meters,
+			Command::GainMinus => &self.rm8.gain_minus,
+			Command::GainPlus => &self.rm8.gain_plus,
+			Command::Mute => &self.rm8.mute,
+			Command::Layout => &self.rm8.layout,
+			Command::Focus => &self.rm8.focus,
+			Command::Diagnostics => &self.rm8.diagnostics,
+			Command::MacroRecord => &self.rm8.macro_record,
+			Command::MacroPlay => &self.rm8.macro_play,
+			Command::Config => &self.rm8.config,
+			Command::Escape => &self.rm8.escape,
+			Command::Fullscreen => &self.rm8.fullscreen,
+			Command::Reset => &self.rm8.reset,
+			Command::ResetFull => &self.rm8.reset_full,
+		})
+	}
+
+	pub fn binding_mut(&mut self, cmd: Command) -> Option<&mut Bindings> {
+		Some(match cmd {
+			Command::None => return None,
+			Command::Up => &mut self.m8.up,
+			Command::Down => &mut self.m8.down,
+			Command::Left => &mut self.m8.left,
+			Command::Right => &mut self.m8.right,
+			Command::Edit => &mut self.m8.edit,
+			Command::r#Option => &mut self.m8.option,
+			Command::Shift => &mut self.m8.shift,
+			Command::Play => &mut self.m8.play,
+			Command::Keyjazz => &mut self.rm8.keyjazz,
+			Command::VelocityMinus => &mut self.rm8.velocity_minus,
+			Command::VelocityPlus => &mut self.rm8.velocity_plus,
+			Command::OctaveMinus => &mut self.rm8.octave_minus,
+			Command::OctavePlus => &mut self.rm8.octave_plus,
+			Command::Screenshot => &mut self.rm8.screenshot,
+			Command::Record => &mut self.rm8.record,
+			Command::RecordAudio => &mut self.rm8.record_audio,
+			Command::Meters => &mut self.rm8.meters,
+			Command::GainMinus => &mut self.rm8.gain_minus,
+			Command::GainPlus => &mut self.rm8.gain_plus,
+			Command::Mute => &mut self.rm8.mute,
+			Command::Layout => &mut self.rm8.layout,
+			Command::Focus => &mut self.rm8.focus,
+			Command::Diagnostics => &mut self.rm8.diagnostics,
+			Command::MacroRecord => &mut self.rm8.macro_record,
+			Command::MacroPlay => &mut self.rm8.macro_play,
+			Command::Config => &mut self.rm8.config,
+			Command::Escape => &mut self.rm8.escape,
+			Command::Fullscreen => &mut self.rm8.fullscreen,
+			Command::Reset => &mut self.rm8.reset,
+			Command::ResetFull => &mut self.rm8.reset_full,
+		})
+	}
+
+	/// Returns the command of the most specific binding matching the key pressed, the first one
+	/// of the list if several are as specific, with the number of modifiers of the binding.
+	pub fn key_pressed(&self, key: SdlKeycode, keymod: Mod) -> Option<(Command, u32)> {
+		self.key_bindings()
+			.into_iter()
+			.rev()
+			.filter_map(|(b, cmd)| b.pressed(key, keymod).map(|n| (cmd, n)))
+			.max_by_key(|(_, n)| *n)
+	}
+
+	/// Returns the index of the macro with the most specific binding matching the key pressed.
+	pub fn macro_pressed(&self, key: SdlKeycode, keymod: Mod) -> Option<usize> {
+		self.macros
+			.iter()
+			.enumerate()
+			.rev()
+			.filter_map(|(i, m)| m.keys.pressed(key, keymod).map(|n| (n, i)))
+			.max_by_key(|(n, _)| *n)
+			.map(|(_, i)| i)
+	}
+
+	/// Returns the commands of all the bindings using the key released, as the modifiers may have
+	/// been released first.
+	pub fn key_released(&self, key: SdlKeycode) -> Vec<Command> {
+		self.key_bindings()
+			.into_iter()
+			.filter(|(b, _)| b.released(key))
+			.map(|(_, cmd)| cmd)
+			.collect()
+	}
+
+	fn check_screen(&mut self) {
+		if let Some(screen) = self.screen {
+			let clamped = screen.clamped();
+			if clamped != screen {
+				eprintln!(
+					"screen size {}x{} too small, using {}x{}",
+					screen.width, screen.height, clamped.width, clamped.height
+				);
+				self.screen = Some(clamped);
+			}
+		}
+	}
+
 	fn check_overlap(&mut self) {
-		self.overlap = self.keyjazz.contains_key(&self.m8.up)
-			|| self.keyjazz.contains_key(&self.m8.down)
-			|| self.keyjazz.contains_key(&self.m8.left)
-			|| self.keyjazz.contains_key(&self.m8.right)
-			|| self.keyjazz.contains_key(&self.m8.shift)
-			|| self.keyjazz.contains_key(&self.m8.play)
-			|| self.keyjazz.contains_key(&self.m8.edit)
-			|| self.keyjazz.contains_key(&self.m8.option)
-			|| self.keyjazz.contains_key(&self.rm8.keyjazz)
-			|| self.keyjazz.contains_key(&self.rm8.octave_minus)
-			|| self.keyjazz.contains_key(&self.rm8.octave_plus)
-			|| self.keyjazz.contains_key(&self.rm8.velocity_minus)
-			|| self.keyjazz.contains_key(&self.rm8.velocity_plus);
+		self.overlap = self
+			.key_bindings()
+			.iter()
+			.any(|(b, _)| b.plain_keys().any(|k| self.keyjazz.contains_key(k)));
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn raises_small_screens() {
+		let mut config =
+			Config { screen: Some(ScreenConfig { width: 0, height: 480 }), ..Config::default() };
+		config.check_screen();
+		assert_eq!(config.screen, Some(ScreenConfig { width: 320, height: 480 }));
+		config.screen = None;
+		config.check_screen();
+		assert_eq!(config.screen, None);
 	}
 }
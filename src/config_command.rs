@@ -1,4 +1,4 @@
-use std::fmt;
+use std::{fmt, ops::Range};
 
 #[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
 pub enum Command {
@@ -21,6 +21,18 @@ pub enum Command {
 	Fullscreen,
 	Reset,
 	ResetFull,
+	Screenshot,
+	Record,
+	RecordAudio,
+	Meters,
+	GainMinus,
+	GainPlus,
+	Mute,
+	Layout,
+	Focus,
+	Diagnostics,
+	MacroRecord,
+	MacroPlay,
 }
 
 impl Default for Command {
@@ -30,38 +42,69 @@ impl Default for Command {
 }
 
 impl Command {
-	pub const MAX_LENGTH: usize = 9;
+	pub const MAX_LENGTH: usize = 10;
+
+	/// Every command with its name, in the order of the menus: the keys of the M8, then the
+	/// ones of rm8 and of the app.
+	pub const ALL: [(Command, &'static str); 30] = [
+		(Command::Up, "UP"),
+		(Command::Down, "DOWN"),
+		(Command::Left, "LEFT"),
+		(Command::Right, "RIGHT"),
+		(Command::Edit, "EDIT"),
+		(Command::r#Option, "OPTION"),
+		(Command::Shift, "SHIFT"),
+		(Command::Play, "PLAY"),
+		(Command::Keyjazz, "KEYJAZZ"),
+		(Command::VelocityMinus, "VELOCITY-"),
+		(Command::VelocityPlus, "VELOCITY+"),
+		(Command::OctaveMinus, "OCTAVE-"),
+		(Command::OctavePlus, "OCTAVE+"),
+		(Command::Screenshot, "SCREENSHOT"),
+		(Command::Record, "RECORD"),
+		(Command::RecordAudio, "REC AUDIO"),
+		(Command::Meters, "METERS"),
+		(Command::GainMinus, "GAIN-"),
+		(Command::GainPlus, "GAIN+"),
+		(Command::Mute, "MUTE"),
+		(Command::Layout, "LAYOUT"),
+		(Command::Focus, "FOCUS"),
+		(Command::Diagnostics, "DIAGNOSTIC"),
+		(Command::MacroRecord, "MACRO REC"),
+		(Command::MacroPlay, "MACRO PLAY"),
+		(Command::Config, "CONFIG"),
+		(Command::Escape, "ESCAPE"),
+		(Command::Fullscreen, "FULLSCREEN"),
+		(Command::Reset, "RESET"),
+		(Command::ResetFull, "RESET FULL"),
+	];
+	/// The commands of the keys of the M8 in `ALL`.
+	pub const M8_KEYS: Range<usize> = 0..8;
+	/// The commands of rm8 in `ALL`, the ones of the app following.
+	pub const RM8_KEYS: Range<usize> = 8..22;
+	pub const APP_KEYS: Range<usize> = 22..30;
 
 	pub fn is_none(&self) -> bool {
 		self == &Self::None
 	}
-}
 
-impl TryFrom<u8> for Command {
-	type Error = ();
-	fn try_from(value: u8) -> Result<Self, Self::Error> {
-		Ok(match value {
-			0 => Command::None,
-			1 => Command::Up,
-			2 => Command::Down,
-			3 => Command::Left,
-			4 => Command::Right,
-			5 => Command::Edit,
-			6 => Command::r#Option,
-			7 => Command::Shift,
-			8 => Command::Play,
-			9 => Command::VelocityMinus,
-			10 => Command::VelocityPlus,
-			11 => Command::OctaveMinus,
-			12 => Command::OctavePlus,
-			13 => Command::Keyjazz,
-			14 => Command::Config,
-			15 => Command::Escape,
-			16 => Command::Fullscreen,
-			17 => Command::Reset,
-			18 => Command::ResetFull,
-			_ => return Err(()),
-		})
+	pub fn name(&self) -> &'static str {
+		Self::ALL.iter().find(|(cmd, _)| cmd == self).map(|(_, name)| *name).unwrap_or("")
+	}
+
+	/// Returns the command following this one in `ALL`, `None` coming before the first one.
+	pub fn next(&self) -> Option<Self> {
+		let pos = Self::ALL.iter().position(|(cmd, _)| cmd == self).map(|pos| pos + 1);
+		Self::ALL.get(pos.unwrap_or(0)).map(|(cmd, _)| *cmd)
+	}
+
+	/// Returns the command preceding this one in `ALL`, `None` coming before the first one.
+	pub fn prev(&self) -> Option<Self> {
+		match Self::ALL.iter().position(|(cmd, _)| cmd == self) {
+			Some(0) => Some(Self::None),
+			Some(pos) => Some(Self::ALL[pos - 1].0),
+			None => None,
+		}
 	}
 }
 
@@ -69,24 +112,28 @@ impl fmt::Display for Command {
 	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
 		match *self {
 			Command::None => write!(f, "{:1$}", " ", Command::MAX_LENGTH),
-			Command::Up => write!(f, "UP"),
-			Command::Down => write!(f, "DOWN"),
-			Command::Left => write!(f, "LEFT"),
-			Command::Right => write!(f, "RIGHT"),
-			Command::Edit => write!(f, "EDIT"),
-			Command::r#Option => write!(f, "OPTION"),
-			Command::Shift => write!(f, "SHIFT"),
-			Command::Play => write!(f, "PLAY"),
-			Command::VelocityMinus => write!(f, "VELOCITY-"),
-			Command::VelocityPlus => write!(f, "VELOCITY+"),
-			Command::OctaveMinus => write!(f, "OCTAVE-"),
-			Command::OctavePlus => write!(f, "OCTAVE+"),
-			Command::Keyjazz => write!(f, "KEYJAZZ"),
-			Command::Config => write!(f, "CONFIG"),
-			Command::Escape => write!(f, "ESCAPE"),
-			Command::Fullscreen => write!(f, "FULLSCREEN"),
-			Command::Reset => write!(f, "RESET"),
-			Command::ResetFull=> write!(f, "RESETFULL"),
+			_ => write!(f, "{}", self.name()),
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn lists_every_command_once() {
+		let mut cmd = Command::None;
+		let mut count = 0;
+		while let Some(next) = cmd.next() {
+			assert_eq!(next.prev(), Some(cmd));
+			assert!(next.name().len() <= Command::MAX_LENGTH);
+			cmd = next;
+			count += 1;
 		}
+		assert_eq!(count, Command::ALL.len());
+		assert_eq!(Command::None.prev(), None);
+		let ranges = [Command::M8_KEYS, Command::RM8_KEYS, Command::APP_KEYS];
+		assert_eq!(ranges.iter().map(|r| r.len()).sum::<usize>(), Command::ALL.len());
 	}
 }
@@ -10,6 +10,28 @@ pub enum Font {
 
 impl Font {
 	pub const MAX_LENGTH: usize = 14;
+
+	/// Returns the character of the font drawn for `c`.
+	pub fn map(self, c: u8) -> u8 {
+		match self {
+			Font::UpperAltZero if c == b'0' => 125,
+			Font::LowerAltZero if c == b'0' => 123,
+			Font::Uppercase | Font::UpperAltZero => {
+				if (b'a'..=b'z').contains(&c) {
+					c - 32
+				} else {
+					c
+				}
+			}
+			Font::Lowercase | Font::LowerAltZero => {
+				if (b'A'..=b'Z').contains(&c) {
+					c + 32
+				} else {
+					c
+				}
+			}
+		}
+	}
 }
 
 impl TryFrom<u8> for Font {
@@ -1,5 +1,6 @@
-use sdl2::keyboard::Keycode as SdlKeycode;
+use sdl2::keyboard::{Keycode as SdlKeycode, Mod};
 use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use std::{fmt, str::FromStr};
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct Keycode(pub SdlKeycode);
@@ -38,3 +39,229 @@ impl<'de> Deserialize<'de> for Keycode {
 		}
 	}
 }
+
+/// The modifiers of a binding, left and right keys being the same.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct Modifiers(u8);
+
+impl Modifiers {
+	pub const CTRL: Self = Self(1);
+	pub const SHIFT: Self = Self(2);
+	pub const ALT: Self = Self(4);
+	pub const GUI: Self = Self(8);
+	const NAMES: [(Self, &'static str); 4] =
+		[(Self::CTRL, "Ctrl"), (Self::SHIFT, "Shift"), (Self::ALT, "Alt"), (Self::GUI, "Gui")];
+
+	pub fn contains(self, other: Self) -> bool {
+		self.0 & other.0 == other.0
+	}
+
+	/// Returns the number of modifiers, the more the more specific the binding.
+	pub fn count(self) -> u32 {
+		self.0.count_ones()
+	}
+
+	/// Returns the modifier pressed by the key itself, if any.
+	fn of_key(key: SdlKeycode) -> Self {
+		match key {
+			SdlKeycode::LCtrl | SdlKeycode::RCtrl => Self::CTRL,
+			SdlKeycode::LShift | SdlKeycode::RShift => Self::SHIFT,
+			SdlKeycode::LAlt | SdlKeycode::RAlt => Self::ALT,
+			SdlKeycode::LGui | SdlKeycode::RGui => Self::GUI,
+			_ => Self::default(),
+		}
+	}
+}
+
+impl From<Mod> for Modifiers {
+	fn from(keymod: Mod) -> Self {
+		let mut mods = 0;
+		if keymod.intersects(Mod::LCTRLMOD | Mod::RCTRLMOD) {
+			mods |= Self::CTRL.0;
+		}
+		if keymod.intersects(Mod::LSHIFTMOD | Mod::RSHIFTMOD) {
+			mods |= Self::SHIFT.0;
+		}
+		if keymod.intersects(Mod::LALTMOD | Mod::RALTMOD) {
+			mods |= Self::ALT.0;
+		}
+		if keymod.intersects(Mod::LGUIMOD | Mod::RGUIMOD) {
+			mods |= Self::GUI.0;
+		}
+		Self(mods)
+	}
+}
+
+/// A key and the modifiers to hold with it, written like `Ctrl+S` or `Shift+Left`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Binding {
+	pub key: Keycode,
+	pub mods: Modifiers,
+}
+
+impl Binding {
+	/// Makes a binding from a key and the modifiers held, the key not being its own modifier.
+	pub fn new(key: SdlKeycode, keymod: Mod) -> Self {
+		let mods = Modifiers::from(keymod).0 & !Modifiers::of_key(key).0;
+		Self { key: Keycode(key), mods: Modifiers(mods) }
+	}
+
+	/// Returns true if the key is a modifier itself, like `LCtrl`.
+	pub fn is_modifier(&self) -> bool {
+		Modifiers::of_key(*self.key).count() > 0
+	}
+
+	/// Returns true if the key is pressed while at least the modifiers of the binding are held.
+	pub fn matches(&self, key: SdlKeycode, mods: Modifiers) -> bool {
+		*self.key == key && mods.contains(self.mods)
+	}
+}
+
+impl From<SdlKeycode> for Binding {
+	fn from(key: SdlKeycode) -> Self {
+		Self { key: Keycode(key), mods: Modifiers::default() }
+	}
+}
+
+impl fmt::Display for Binding {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		for (m, name) in Modifiers::NAMES {
+			if self.mods.contains(m) {
+				write!(f, "{}+", name)?;
+			}
+		}
+		write!(f, "{}", self.key.name())
+	}
+}
+
+impl FromStr for Binding {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		let mut rest = s;
+		let mut mods = 0;
+		'strip: loop {
+			for (m, name) in Modifiers::NAMES {
+				// the key itself may be `+`, as in `Ctrl++`
+				if let Some(r) = rest.strip_prefix(name).and_then(|r| r.strip_prefix('+')) {
+					if !r.is_empty() {
+						mods |= m.0;
+						rest = r;
+						continue 'strip;
+					}
+				}
+			}
+			break;
+		}
+		match SdlKeycode::from_name(rest) {
+			Some(key) => Ok(Self { key: Keycode(key), mods: Modifiers(mods) }),
+			None => Err(format!("Invalid key: {}", s)),
+		}
+	}
+}
+
+/// The bindings of an action, any of them triggering it. A single binding is written as a string
+/// as in the older configurations, several ones as a list.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Bindings(pub Vec<Binding>);
+
+impl Bindings {
+	/// Returns the number of modifiers of the most specific binding matching the key pressed.
+	pub fn pressed(&self, key: SdlKeycode, keymod: Mod) -> Option<u32> {
+		let mods = Modifiers::from(keymod);
+		self.0.iter().filter(|b| b.matches(key, mods)).map(|b| b.mods.count()).max()
+	}
+
+	/// Returns true if one of the bindings uses the key released, whatever the modifiers now.
+	pub fn released(&self, key: SdlKeycode) -> bool {
+		self.0.iter().any(|b| *b.key == key)
+	}
+
+	pub fn contains(&self, binding: &Binding) -> bool {
+		self.0.contains(binding)
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.0.is_empty()
+	}
+
+	/// Returns the keys bound without any modifier.
+	pub fn plain_keys(&self) -> impl Iterator<Item = &Keycode> {
+		self.0.iter().filter(|b| b.mods.count() == 0).map(|b| &b.key)
+	}
+
+	/// Adds a binding, unless it is already there.
+	pub fn add(&mut self, binding: Binding) {
+		if !self.contains(&binding) {
+			self.0.push(binding);
+		}
+	}
+}
+
+impl From<SdlKeycode> for Bindings {
+	fn from(key: SdlKeycode) -> Self {
+		Self(vec![key.into()])
+	}
+}
+
+impl From<Binding> for Bindings {
+	fn from(binding: Binding) -> Self {
+		Self(vec![binding])
+	}
+}
+
+impl fmt::Display for Bindings {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		for (i, b) in self.0.iter().enumerate() {
+			if i > 0 {
+				write!(f, " ")?;
+			}
+			write!(f, "{}", b)?;
+		}
+		Ok(())
+	}
+}
+
+impl Serialize for Bindings {
+	fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+	where
+		S: Serializer,
+	{
+		if self.0.len() == 1 {
+			serializer.collect_str(&self.0[0])
+		} else {
+			serializer.collect_seq(self.0.iter().map(|b| b.to_string()))
+		}
+	}
+}
+
+struct BindingsVisitor;
+
+impl<'de> de::Visitor<'de> for BindingsVisitor {
+	type Value = Bindings;
+
+	fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		f.write_str("a key or a list of keys")
+	}
+
+	fn visit_str<E: de::Error>(self, s: &str) -> Result<Self::Value, E> {
+		s.parse::<Binding>().map(Bindings::from).map_err(E::custom)
+	}
+
+	fn visit_seq<A: de::SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+		let mut bindings = Bindings::default();
+		while let Some(s) = seq.next_element::<String>()? {
+			bindings.add(s.parse().map_err(de::Error::custom)?);
+		}
+		Ok(bindings)
+	}
+}
+
+impl<'de> Deserialize<'de> for Bindings {
+	fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+	where
+		D: Deserializer<'de>,
+	{
+		deserializer.deserialize_any(BindingsVisitor)
+	}
+}
@@ -0,0 +1,88 @@
+use std::{
+	fs,
+	time::{Duration, Instant},
+};
+
+/// Clock ticks per second of the times in `/proc`, fixed by the kernel ABI.
+const USER_HZ: u64 = 100;
+const PERIOD: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Default, Clone, Copy)]
+struct Share {
+	wall: Duration,
+	cpu: Duration,
+}
+
+impl Share {
+	fn percent(&self) -> Option<f32> {
+		if self.wall.is_zero() {
+			return None;
+		}
+		Some(self.cpu.as_secs_f32() / self.wall.as_secs_f32() * 100.0)
+	}
+}
+
+/// CPU used by rm8, split between the time its display changes (active) and the time it does not
+/// (idle).
+pub struct CpuUsage {
+	ticks: Instant,
+	cpu: Option<Duration>,
+	active: bool,
+	idle_share: Share,
+	active_share: Share,
+}
+
+impl CpuUsage {
+	pub fn new() -> Self {
+		Self {
+			ticks: Instant::now(),
+			cpu: process_time(),
+			active: false,
+			idle_share: Share::default(),
+			active_share: Share::default(),
+		}
+	}
+
+	/// Notes that the display changed during the current period.
+	pub fn activity(&mut self) {
+		self.active = true;
+	}
+
+	/// Counts the last period as active or idle once it is over.
+	pub fn update(&mut self) {
+		let elapsed = self.ticks.elapsed();
+		if elapsed < PERIOD {
+			return;
+		}
+		let cpu = process_time();
+		if let (Some(now), Some(before)) = (cpu, self.cpu) {
+			let share = if self.active { &mut self.active_share } else { &mut self.idle_share };
+			share.wall += elapsed;
+			share.cpu += now.saturating_sub(before);
+		}
+		self.cpu = cpu;
+		self.ticks = Instant::now();
+		self.active = false;
+	}
+
+	/// Returns the average CPU usage while the display changes, in percent of one core.
+	pub fn active(&self) -> Option<f32> {
+		self.active_share.percent()
+	}
+
+	/// Returns the average CPU usage while the display does not change, in percent of one core.
+	pub fn idle(&self) -> Option<f32> {
+		self.idle_share.percent()
+	}
+}
+
+/// Returns the CPU time used by all the threads of rm8 so far, where `/proc` tells it.
+fn process_time() -> Option<Duration> {
+	let stat = fs::read_to_string("/proc/self/stat").ok()?;
+	// the command name, in parentheses, may hold spaces: the fields are counted after it, from
+	// the 3rd one
+	let fields: Vec<&str> = stat.rsplit_once(')')?.1.split_whitespace().collect();
+	let utime: u64 = fields.get(14 - 3)?.parse().ok()?;
+	let stime: u64 = fields.get(15 - 3)?.parse().ok()?;
+	Some(Duration::from_millis((utime + stime) * 1000 / USER_HZ))
+}
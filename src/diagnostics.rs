@@ -0,0 +1,141 @@
+use sdl2::rect::Rect;
+use std::time::{Duration, Instant};
+
+use crate::{
+	cpu::CpuUsage,
+	draw::{Context, LINE_HEIGHT},
+	font,
+	m8::LinkStats,
+};
+
+const PANEL_X: i32 = 8;
+const PANEL_Y: i32 = 24;
+/// Column of the values, in characters.
+const VALUE_X: i32 = 7;
+/// Period over which the rates are computed.
+const PERIOD: Duration = Duration::from_secs(1);
+
+/// Shows the health of the link with an M8: what it sends, what goes wrong and what is sent
+/// back to it.
+pub struct Diagnostics {
+	previous: LinkStats,
+	ticks: Instant,
+	/// Counters over the last period.
+	rates: LinkStats,
+}
+
+impl Diagnostics {
+	pub fn new() -> Self {
+		Self { previous: LinkStats::default(), ticks: Instant::now(), rates: LinkStats::default() }
+	}
+
+	/// Restarts the rates from the current counters, when showing another M8.
+	pub fn reset(&mut self, stats: LinkStats) {
+		self.previous = stats;
+		self.ticks = Instant::now();
+		self.rates = LinkStats::default();
+	}
+
+	pub fn update(&mut self, stats: LinkStats) {
+		let elapsed = self.ticks.elapsed();
+		if elapsed < PERIOD {
+			return;
+		}
+		let per_second = |now: u64, before: u64| {
+			(now.saturating_sub(before) as f64 / elapsed.as_secs_f64()).round() as u64
+		};
+		let previous = &self.previous;
+		self.rates = LinkStats {
+			bytes: per_second(stats.bytes, previous.bytes),
+			frames: per_second(stats.frames, previous.frames),
+			joypads: per_second(stats.joypads, previous.joypads),
+			waveforms: per_second(stats.waveforms, previous.waveforms),
+			characters: per_second(stats.characters, previous.characters),
+			rectangles: per_second(stats.rectangles, previous.rectangles),
+			keys_sent: per_second(stats.keys_sent, previous.keys_sent),
+			notes_sent: per_second(stats.notes_sent, previous.notes_sent),
+			..LinkStats::default()
+		};
+		self.previous = stats;
+		self.ticks = Instant::now();
+	}
+
+	/// Draws the panel with its top left corner at `origin` plus a margin.
+	pub fn draw(
+		&self,
+		ctx: &mut Context<'_, '_, '_>,
+		stats: &LinkStats,
+		origin: (i32, i32),
+		cpu: &CpuUsage,
+		damage: Option<Rect>,
+	) -> Result<(), String> {
+		let theme = ctx.theme;
+		let (x, y) = (origin.0 + PANEL_X, origin.1 + PANEL_Y);
+		let link = match (stats.lost, stats.reconnect) {
+			(false, _) => "CONNECTED",
+			(true, true) => "LOST, RECONNECTING",
+			(true, false) => "LOST",
+		};
+		let idle = match stats.idle {
+			Some(idle) => format!("{}MS", idle.as_millis().min(99999)),
+			None => "-".to_string(),
+		};
+		let model = match stats.info {
+			Some(info) => {
+				let (major, minor, patch) = info.firmware;
+				format!("{} {}.{}.{}", info.model, major, minor, patch).to_uppercase()
+			}
+			None => "-".to_string(),
+		};
+		let percent = |usage: Option<f32>| match usage {
+			Some(usage) => format!("{:.1}%", usage),
+			None => "-".to_string(),
+		};
+		let drawn = match damage {
+			Some(r) => format!("{}X{} AT {},{}", r.width(), r.height(), r.x(), r.y()),
+			None => "-".to_string(),
+		};
+		let errors = stats.malformed + stats.overflows + stats.invalid + stats.unknown;
+		let rates = &self.rates;
+		let lines = [
+			("LINK", link.to_string(), stats.lost),
+			("M8", model, false),
+			("IN", format!("{} B/S {} FRAMES/S", rates.bytes, rates.frames), false),
+			("RECT", format!("{}/S CHAR {}/S", rates.rectangles, rates.characters), false),
+			("WAVE", format!("{}/S JOY {}/S", rates.waveforms, rates.joypads), false),
+			("IDLE", idle, false),
+			(
+				"ERRORS",
+				format!(
+					"MAL {} OVF {} INV {} UNK {}",
+					stats.malformed, stats.overflows, stats.invalid, stats.unknown
+				),
+				errors > 0,
+			),
+			(
+				"OUT",
+				format!(
+					"C {} ({}/S) K {} ({}/S)",
+					stats.keys_sent, rates.keys_sent, stats.notes_sent, rates.notes_sent
+				),
+				false,
+			),
+			("DRAWN", drawn, false),
+			(
+				"CPU",
+				format!("ACTIVE {} IDLE {}", percent(cpu.active()), percent(cpu.idle())),
+				false,
+			),
+		];
+		let height = (lines.len() as i32 * LINE_HEIGHT + 4) as u32;
+		ctx.draw_rect((x, y, ctx.screen.width - 2 * PANEL_X as u32, height), theme.screen)?;
+		for (i, (label, value, alert)) in lines.iter().enumerate() {
+			let line_y = y + i as i32 * LINE_HEIGHT;
+			let value_x = x + 2 + VALUE_X * font::CHAR_WIDTH;
+			let fg = if *alert { theme.cursor } else { theme.text_value };
+			ctx.draw_str(label, x + 2, line_y, theme.text_title, theme.text_title)?;
+			ctx.draw_str(value, value_x, line_y, fg, fg)?;
+		}
+		Ok(())
+	}
+}
@@ -1,10 +1,11 @@
 use sdl2::{
+	pixels::PixelFormatEnum,
 	rect, render,
 	video::{FullscreenType, Window},
 };
 
 use crate::{
-	config::{Font, Rgb, ThemeConfig},
+	config::{Font, Layout, Rgb, ThemeConfig},
 	font, m8,
 };
 
@@ -16,15 +17,45 @@ pub struct Context<'a, 'b, 'c> {
 	pub font_option: Font,
 	pub theme: ThemeConfig,
 	pub screen_bg: Option<Rgb>,
+	pub screen: m8::Screen,
+	pub damage: Damage,
+}
+
+/// Part of a screen drawn since it was last shown.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Damage(Option<rect::Rect>);
+
+impl Damage {
+	pub fn add(&mut self, rect: rect::Rect) {
+		self.0 = Some(match self.0 {
+			Some(damage) => damage.union(rect),
+			None => rect,
+		});
+	}
+
+	pub fn merge(&mut self, other: Damage) {
+		if let Some(rect) = other.0 {
+			self.add(rect);
+		}
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.0.is_none()
+	}
+
+	/// Returns the damaged area, forgetting it.
+	pub fn take(&mut self) -> Option<rect::Rect> {
+		self.0.take()
+	}
 }
 
 impl<'a, 'b, 'c> Context<'_, '_, '_> {
 	pub fn clear(&mut self) -> Result<(), String> {
-		self.draw_rect((0, 0, m8::SCREEN_WIDTH, m8::SCREEN_HEIGHT), self.theme.screen)
+		self.draw_rect((0, 0, self.screen.width, self.screen.height), self.theme.screen)
 	}
 
 	pub fn draw_str_centered(&mut self, s: &str, y: i32, fg: Rgb, bg: Rgb) -> Result<(), String> {
-		let x = (m8::SCREEN_WIDTH as usize / 2) as i32 - font::width(s.len() / 2);
+		let x = (self.screen.width as usize / 2) as i32 - font::width(s.len() / 2);
 		self.draw_str(s, x, y, fg, bg)
 	}
 
@@ -38,24 +69,24 @@ impl<'a, 'b, 'c> Context<'_, '_, '_> {
 	}
 
 	pub fn draw_char(&mut self, c: u8, x: i32, y: i32, fg: Rgb, bg: Rgb) -> Result<(), String> {
-		let c = match self.font_option {
-			Font::UpperAltZero if c == b'0' => 125,
-			Font::LowerAltZero if c == b'0' => 123,
-			Font::Uppercase | Font::UpperAltZero => {
-				if (b'a'..=b'z').contains(&c) {
-					c - 32
-				} else {
-					c
-				}
-			}
-			Font::Lowercase | Font::LowerAltZero => {
-				if (b'A'..=b'Z').contains(&c) {
-					c + 32
-				} else {
-					c
-				}
-			}
-		};
+		self.draw_glyph(c, x, y, fg, bg, font::Glyphs::SMALL)
+	}
+
+	/// Draws a character sent by the M8, in the font it uses.
+	pub fn draw_m8_char(&mut self, c: u8, x: i32, y: i32, fg: Rgb, bg: Rgb) -> Result<(), String> {
+		self.draw_glyph(c, x, y, fg, bg, self.screen.glyphs)
+	}
+
+	fn draw_glyph(
+		&mut self,
+		c: u8,
+		x: i32,
+		y: i32,
+		fg: Rgb,
+		bg: Rgb,
+		glyphs: font::Glyphs,
+	) -> Result<(), String> {
+		let c = self.font_option.map(c);
 		let row = c as i32 / font::CHARS_BY_ROW;
 		let col = c as i32 % font::CHARS_BY_ROW;
 		let src_rect = rect::Rect::new(
@@ -64,49 +95,55 @@ impl<'a, 'b, 'c> Context<'_, '_, '_> {
 			font::CHAR_WIDTH as u32,
 			font::CHAR_HEIGHT as u32,
 		);
-		let dst_rect = rect::Rect::new(x, y + 3, src_rect.w as u32, src_rect.h as u32);
+		let dst_rect =
+			rect::Rect::new(x, y + glyphs.offset_y, glyphs.width as u32, glyphs.height as u32);
 		self.font.set_color_mod(fg.0, fg.1, fg.2);
+		self.damage.add(dst_rect);
 		if fg != bg {
 			let bg_rect = rect::Rect::new(
-				x as i32 - 1,
-				y as i32 + 2,
-				font::CHAR_WIDTH as u32 - 1,
-				font::CHAR_HEIGHT as u32 + 1,
+				x - 1,
+				y + glyphs.offset_y - 1,
+				glyphs.width as u32 - 1,
+				glyphs.height as u32 + 1,
 			);
 			self.canvas.set_draw_color(bg.rgb());
 			self.canvas.fill_rect(bg_rect)?;
+			self.damage.add(bg_rect);
 		}
 		self.canvas.copy(self.font, src_rect, dst_rect)
 	}
 
 	pub fn draw_rect(&mut self, rect: (i32, i32, u32, u32), bg: Rgb) -> Result<(), String> {
 		let r = rect::Rect::new(rect.0, rect.1, rect.2, rect.3);
-		if rect.0 == 0 && rect.1 == 0 && rect.2 == m8::SCREEN_WIDTH && rect.3 == m8::SCREEN_HEIGHT {
+		if rect.0 == 0 && rect.1 == 0 && rect.2 == self.screen.width && rect.3 == self.screen.height
+		{
 			self.screen_bg = Some(bg);
 		}
 		self.canvas.set_draw_color(bg.rgb());
+		self.damage.add(r);
 		self.canvas.fill_rect(r)
 	}
 
 	pub fn draw_waveform(&mut self, data: &[u8], fg: (u8, u8, u8)) -> Result<(), String> {
 		self.canvas.set_draw_color(self.theme.screen.rgb());
-		let rect = rect::Rect::new(0, 0, m8::SCREEN_WIDTH, m8::WAVEFORM_HEIGHT);
+		// as high as the highest waveform yet, this one included
+		let height = data.iter().map(|p| *p as u32 + 1).fold(self.screen.waveform_height, u32::max);
+		let rect = rect::Rect::new(0, 0, self.screen.width, height);
 		self.canvas.fill_rect(rect)?;
+		self.damage.add(rect);
 		if data.is_empty() {
 			return Ok(());
 		}
 		self.canvas.set_draw_color(fg);
 
-		let mut points = [rect::Point::new(0, 0); m8::SCREEN_WIDTH as usize];
-		for (i, p) in data.iter().enumerate() {
-			points[i] = rect::Point::new(i as i32, *p as i32);
-		}
-		self.canvas.draw_points(points.as_ref())
+		let points: Vec<_> =
+			data.iter().enumerate().map(|(i, p)| rect::Point::new(i as i32, *p as i32)).collect();
+		self.canvas.draw_points(points.as_slice())
 	}
 
 	pub fn draw_octave(&mut self, octave: u8, show: bool) -> Result<(), String> {
-		let x = m8::SCREEN_WIDTH as i32 - font::CHAR_WIDTH;
-		let y = m8::SCREEN_HEIGHT as i32 - font::CHAR_HEIGHT;
+		let x = self.screen.width as i32 - font::CHAR_WIDTH;
+		let y = self.screen.height as i32 - font::CHAR_HEIGHT;
 
 		let rect = rect::Rect::new(
 			x + 1,
@@ -116,6 +153,7 @@ impl<'a, 'b, 'c> Context<'_, '_, '_> {
 		);
 		self.canvas.set_draw_color(self.theme.screen.rgb());
 		self.canvas.fill_rect(rect)?;
+		self.damage.add(rect);
 
 		if show {
 			let c = if octave >= 9 { octave - 9 + b'A' } else { octave + b'1' };
@@ -129,8 +167,8 @@ impl<'a, 'b, 'c> Context<'_, '_, '_> {
 	}
 
 	pub fn draw_velocity(&mut self, velocity: u8, show: bool) -> Result<(), String> {
-		let mut x = m8::SCREEN_WIDTH as i32 - font::CHAR_WIDTH * 3 + 2;
-		let y = m8::SCREEN_HEIGHT as i32 - font::CHAR_HEIGHT;
+		let mut x = self.screen.width as i32 - font::CHAR_WIDTH * 3 + 2;
+		let y = self.screen.height as i32 - font::CHAR_HEIGHT;
 
 		let rect = rect::Rect::new(
 			x as i32 - 1,
@@ -140,6 +178,7 @@ impl<'a, 'b, 'c> Context<'_, '_, '_> {
 		);
 		self.canvas.set_draw_color(self.theme.screen.rgb());
 		self.canvas.fill_rect(rect)?;
+		self.damage.add(rect);
 
 		if show {
 			let (vh, vl) = (velocity >> 4, velocity & 0xf);
@@ -166,9 +205,58 @@ pub fn toggle_fullscreen(canvas: &mut render::Canvas<Window>) -> Result<(), Stri
 	}
 }
 
-pub fn zoom_window(window: &mut Window, zoom: u32) {
-	let (w, _) = window.size();
-	if m8::SCREEN_WIDTH * zoom != w {
-		let _ = window.set_size(zoom * m8::SCREEN_WIDTH, zoom * m8::SCREEN_HEIGHT);
+/// Sizes the window for a logical size.
+pub fn zoom_window(window: &mut Window, zoom: u32, size: (u32, u32)) {
+	let size = (zoom * size.0, zoom * size.1);
+	if window.size() != size {
+		let _ = window.set_size(size.0, size.1);
+	}
+}
+
+/// Returns the number of columns and rows of screens shown with a layout.
+pub fn layout_grid(layout: Layout, screens: usize) -> (u32, u32) {
+	let screens = screens.max(1) as u32;
+	match layout {
+		Layout::Single => (1, 1),
+		Layout::SideBySide => (screens, 1),
+		Layout::Tiled => {
+			let cols = (1..screens).find(|cols| cols * cols >= screens).unwrap_or(screens);
+			(cols, screens.div_ceil(cols))
+		}
+	}
+}
+
+/// Returns the screens shown with a layout and where they go, in logical coordinates, each one
+/// taking `size`.
+pub fn layout_tiles(
+	layout: Layout,
+	screens: usize,
+	focus: usize,
+	size: (u32, u32),
+) -> Vec<(usize, rect::Rect)> {
+	let (w, h) = size;
+	if layout == Layout::Single {
+		return vec![(focus, rect::Rect::new(0, 0, w, h))];
 	}
+	let (cols, _) = layout_grid(layout, screens);
+	(0..screens)
+		.map(|i| {
+			let (col, row) = (i as u32 % cols, i as u32 / cols);
+			(i, rect::Rect::new((col * w) as i32, (row * h) as i32, w, h))
+		})
+		.collect()
+}
+
+/// Reads back the ARGB8888 pixels of a target texture.
+pub fn read_pixels(
+	canvas: &mut render::Canvas<Window>,
+	texture: &mut render::Texture<'_>,
+) -> Result<Vec<u8>, String> {
+	let mut pixels = Ok(vec![]);
+	canvas
+		.with_texture_canvas(texture, |target| {
+			pixels = target.read_pixels(None, PixelFormatEnum::ARGB8888);
+		})
+		.map_err(|e| e.to_string())?;
+	pixels
 }
@@ -0,0 +1,277 @@
+use std::{
+	f32::consts::PI,
+	sync::{
+		atomic::{AtomicBool, AtomicI32, AtomicU32, Ordering},
+		Arc,
+	},
+};
+
+use crate::config::AudioFxConfig;
+
+const LOW_FREQ: f32 = 200.0;
+const MID_FREQ: f32 = 1000.0;
+const HIGH_FREQ: f32 = 4000.0;
+const MID_Q: f32 = 0.7;
+const SHELF_SLOPE: f32 = 1.0;
+/// Time for the limiter to recover from a gain reduction, in seconds.
+const RELEASE: f32 = 0.05;
+
+/// Parameters of the effects chain, shared between the application and the audio thread.
+#[derive(Default)]
+pub struct Params {
+	gain: AtomicI32,
+	mute: AtomicBool,
+	limiter: AtomicBool,
+	threshold: AtomicI32,
+	low: AtomicI32,
+	mid: AtomicI32,
+	high: AtomicI32,
+	version: AtomicU32,
+}
+
+impl Params {
+	pub fn set(&self, config: &AudioFxConfig) {
+		self.gain.store(config.gain, Ordering::Relaxed);
+		self.mute.store(config.mute, Ordering::Relaxed);
+		self.limiter.store(config.limiter, Ordering::Relaxed);
+		self.threshold.store(config.threshold, Ordering::Relaxed);
+		self.low.store(config.low, Ordering::Relaxed);
+		self.mid.store(config.mid, Ordering::Relaxed);
+		self.high.store(config.high, Ordering::Relaxed);
+		self.version.fetch_add(1, Ordering::Release);
+	}
+}
+
+#[derive(Clone, Copy, Default)]
+struct Biquad {
+	b0: f32,
+	b1: f32,
+	b2: f32,
+	a1: f32,
+	a2: f32,
+	x1: f32,
+	x2: f32,
+	y1: f32,
+	y2: f32,
+}
+
+impl Biquad {
+	/// Sets the coefficients (normalized by a0), keeping the state so that changing the
+	/// parameters while playing does not click.
+	fn set(&mut self, b: [f32; 3], a: [f32; 3]) {
+		self.b0 = b[0] / a[0];
+		self.b1 = b[1] / a[0];
+		self.b2 = b[2] / a[0];
+		self.a1 = a[1] / a[0];
+		self.a2 = a[2] / a[0];
+	}
+
+	// the following filters come from the Audio EQ Cookbook by Robert Bristow-Johnson
+
+	fn low_shelf(&mut self, rate: f32, freq: f32, db: f32) {
+		let (a, cos, alpha) = shelf(rate, freq, db);
+		let sq = 2.0 * a.sqrt() * alpha;
+		self.set(
+			[
+				a * ((a + 1.0) - (a - 1.0) * cos + sq),
+				2.0 * a * ((a - 1.0) - (a + 1.0) * cos),
+				a * ((a + 1.0) - (a - 1.0) * cos - sq),
+			],
+			[
+				(a + 1.0) + (a - 1.0) * cos + sq,
+				-2.0 * ((a - 1.0) + (a + 1.0) * cos),
+				(a + 1.0) + (a - 1.0) * cos - sq,
+			],
+		);
+	}
+
+	fn high_shelf(&mut self, rate: f32, freq: f32, db: f32) {
+		let (a, cos, alpha) = shelf(rate, freq, db);
+		let sq = 2.0 * a.sqrt() * alpha;
+		self.set(
+			[
+				a * ((a + 1.0) + (a - 1.0) * cos + sq),
+				-2.0 * a * ((a - 1.0) + (a + 1.0) * cos),
+				a * ((a + 1.0) + (a - 1.0) * cos - sq),
+			],
+			[
+				(a + 1.0) - (a - 1.0) * cos + sq,
+				2.0 * ((a - 1.0) - (a + 1.0) * cos),
+				(a + 1.0) - (a - 1.0) * cos - sq,
+			],
+		);
+	}
+
+	fn peaking(&mut self, rate: f32, freq: f32, q: f32, db: f32) {
+		let a = 10f32.powf(db / 40.0);
+		let w0 = 2.0 * PI * freq / rate;
+		let alpha = w0.sin() / (2.0 * q);
+		let cos = w0.cos();
+		self.set(
+			[1.0 + alpha * a, -2.0 * cos, 1.0 - alpha * a],
+			[1.0 + alpha / a, -2.0 * cos, 1.0 - alpha / a],
+		);
+	}
+
+	fn process(&mut self, x: f32) -> f32 {
+		let y = self.b0 * x + self.b1 * self.x1 + self.b2 * self.x2
+			- self.a1 * self.y1
+			- self.a2 * self.y2;
+		self.x2 = self.x1;
+		self.x1 = x;
+		self.y2 = self.y1;
+		self.y1 = y;
+		y
+	}
+}
+
+fn shelf(rate: f32, freq: f32, db: f32) -> (f32, f32, f32) {
+	let a = 10f32.powf(db / 40.0);
+	let w0 = 2.0 * PI * freq / rate;
+	let alpha = w0.sin() / 2.0 * ((a + 1.0 / a) * (1.0 / SHELF_SLOPE - 1.0) + 2.0).sqrt();
+	(a, w0.cos(), alpha)
+}
+
+fn db_to_gain(db: i32) -> f32 {
+	10f32.powf(db as f32 / 20.0)
+}
+
+/// Gain, 3-band EQ and brickwall limiter, applied to interleaved stereo samples.
+pub struct Chain {
+	params: Arc<Params>,
+	version: Option<u32>,
+	rate: f32,
+	gain: f32,
+	mute: bool,
+	eq: Option<[[Biquad; 3]; 2]>,
+	limiter: Option<f32>,
+	envelope: f32,
+	release: f32,
+}
+
+impl Chain {
+	pub fn new(params: Arc<Params>, rate: i32) -> Self {
+		let rate = rate as f32;
+		Self {
+			params,
+			version: None,
+			rate,
+			gain: 1.0,
+			mute: false,
+			eq: None,
+			limiter: None,
+			envelope: 1.0,
+			release: 1.0 - (-1.0 / (rate * RELEASE)).exp(),
+		}
+	}
+
+	fn update(&mut self) {
+		let version = self.params.version.load(Ordering::Acquire);
+		if self.version == Some(version) {
+			return;
+		}
+		self.version = Some(version);
+		let params = &self.params;
+		self.gain = db_to_gain(params.gain.load(Ordering::Relaxed));
+		self.mute = params.mute.load(Ordering::Relaxed);
+		self.limiter = if params.limiter.load(Ordering::Relaxed) {
+			Some(db_to_gain(params.threshold.load(Ordering::Relaxed)))
+		} else {
+			None
+		};
+		let (low, mid, high) = (
+			params.low.load(Ordering::Relaxed) as f32,
+			params.mid.load(Ordering::Relaxed) as f32,
+			params.high.load(Ordering::Relaxed) as f32,
+		);
+		if low == 0.0 && mid == 0.0 && high == 0.0 {
+			self.eq = None;
+			return;
+		}
+		let rate = self.rate;
+		let eq = self.eq.get_or_insert([[Biquad::default(); 3]; 2]);
+		for bands in eq.iter_mut() {
+			bands[0].low_shelf(rate, LOW_FREQ, low);
+			bands[1].peaking(rate, MID_FREQ, MID_Q, mid);
+			bands[2].high_shelf(rate, HIGH_FREQ, high);
+		}
+	}
+
+	pub fn process(&mut self, samples: &mut [i16]) {
+		self.update();
+		if self.mute {
+			samples.fill(0);
+			return;
+		}
+		if self.gain == 1.0 && self.eq.is_none() && self.limiter.is_none() {
+			return;
+		}
+		for frame in samples.chunks_exact_mut(2) {
+			let mut out =
+				[frame[0] as f32 / 32768.0 * self.gain, frame[1] as f32 / 32768.0 * self.gain];
+			if let Some(ref mut eq) = self.eq {
+				for (sample, bands) in out.iter_mut().zip(eq.iter_mut()) {
+					*sample = bands.iter_mut().fold(*sample, |x, band| band.process(x));
+				}
+			}
+			if let Some(threshold) = self.limiter {
+				// instant attack so that nothing goes over the threshold, smooth release
+				let peak = out[0].abs().max(out[1].abs());
+				let target = if peak > threshold { threshold / peak } else { 1.0 };
+				if target < self.envelope {
+					self.envelope = target;
+				} else {
+					self.envelope += (target - self.envelope) * self.release;
+				}
+				for sample in out.iter_mut() {
+					*sample = (*sample * self.envelope).clamp(-threshold, threshold);
+				}
+			}
+			frame[0] = (out[0] * 32768.0).clamp(i16::MIN as f32, i16::MAX as f32) as i16;
+			frame[1] = (out[1] * 32768.0).clamp(i16::MIN as f32, i16::MAX as f32) as i16;
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn chain(config: AudioFxConfig) -> Chain {
+		let params = Arc::new(Params::default());
+		params.set(&config);
+		Chain::new(params, 44100)
+	}
+
+	/// A loud stereo sine, boosted further by the gain of the chain.
+	fn sine(frames: usize) -> Vec<i16> {
+		(0..frames)
+			.flat_map(|i| {
+				let sample = ((i as f32 * 0.05).sin() * 30000.0) as i16;
+				[sample, -sample]
+			})
+			.collect()
+	}
+
+	#[test]
+	fn limits_the_samples_to_the_threshold() {
+		let config =
+			AudioFxConfig { gain: 12, limiter: true, threshold: -6, ..AudioFxConfig::default() };
+		let ceiling = (db_to_gain(config.threshold) * 32768.0) as i32 + 1;
+		let mut chain = chain(config);
+		let mut samples = sine(4096);
+		chain.process(&mut samples);
+		assert!(samples.iter().all(|s| (*s as i32).abs() <= ceiling));
+		// the limiter reduces the gain, it does not silence
+		assert!(samples.iter().any(|s| (*s as i32).abs() > ceiling / 2));
+	}
+
+	#[test]
+	fn mutes_whatever_the_other_effects() {
+		let config = AudioFxConfig { gain: 12, mute: true, low: 6, ..AudioFxConfig::default() };
+		let mut chain = chain(config);
+		let mut samples = sine(256);
+		chain.process(&mut samples);
+		assert!(samples.iter().all(|s| *s == 0));
+	}
+}
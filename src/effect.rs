@@ -0,0 +1,92 @@
+use sdl2::{
+	pixels::PixelFormatEnum,
+	rect::Rect,
+	render::{BlendMode, Canvas, Texture, TextureCreator},
+	video::{Window, WindowContext},
+};
+
+use crate::config::Effect;
+
+/// Opacity of the lines.
+const SHADE: u32 = 0x60;
+
+/// Draws the scanlines or the grid over a scaled screen. The lines are computed in software at
+/// the size of the screen in the window, so that they stay one pixel thin whatever the scale and
+/// the renderer.
+pub struct Overlay<'a> {
+	creator: &'a TextureCreator<WindowContext>,
+	texture: Option<Texture<'a>>,
+	/// What the texture was made for: the effect, the size of the screen and its size in the
+	/// window.
+	key: (Effect, (u32, u32), (u32, u32)),
+}
+
+impl<'a> Overlay<'a> {
+	pub fn new(creator: &'a TextureCreator<WindowContext>) -> Self {
+		Self { creator, texture: None, key: (Effect::None, (0, 0), (0, 0)) }
+	}
+
+	/// Draws the effect over the screen of `size` pixels drawn in `rect`.
+	pub fn draw(
+		&mut self,
+		canvas: &mut Canvas<Window>,
+		effect: Effect,
+		size: (u32, u32),
+		rect: Rect,
+	) -> Result<(), String> {
+		if effect == Effect::None {
+			return Ok(());
+		}
+		let (scale_x, scale_y) = canvas.scale();
+		let output = (
+			(rect.width() as f32 * scale_x).round() as u32,
+			(rect.height() as f32 * scale_y).round() as u32,
+		);
+		// no room for a line between the pixels
+		if output.0 < size.0 * 2 || output.1 < size.1 * 2 {
+			return Ok(());
+		}
+		let key = (effect, size, output);
+		if self.texture.is_none() || self.key != key {
+			self.texture = Some(self.build(effect, size, output)?);
+			self.key = key;
+		}
+		match self.texture {
+			Some(ref texture) => canvas.copy(texture, None, rect),
+			None => Ok(()),
+		}
+	}
+
+	fn build(
+		&self,
+		effect: Effect,
+		size: (u32, u32),
+		output: (u32, u32),
+	) -> Result<Texture<'a>, String> {
+		let (width, height) = output;
+		let rows: Vec<bool> = (0..height).map(|y| last(y, size.1, height)).collect();
+		let cols: Vec<bool> =
+			(0..width).map(|x| effect == Effect::Grid && last(x, size.0, width)).collect();
+		let mut pixels = Vec::with_capacity((width * height * 4) as usize);
+		for &row in rows.iter() {
+			for &col in cols.iter() {
+				let argb = if row || col { SHADE << 24 } else { 0 };
+				pixels.extend_from_slice(&argb.to_ne_bytes());
+			}
+		}
+		let mut texture = self
+			.creator
+			.create_texture_static(PixelFormatEnum::ARGB8888, width, height)
+			.map_err(|e| e.to_string())?;
+		texture.update(None, &pixels, width as usize * 4).map_err(|e| e.to_string())?;
+		texture.set_blend_mode(BlendMode::Blend);
+		Ok(texture)
+	}
+}
+
+/// Returns true if the pixel `i` of the window is the last one of a pixel of the screen, the
+/// screen being `n` pixels drawn over `len`.
+fn last(i: u32, n: u32, len: u32) -> bool {
+	let (i, n, len) = (i as u64, n as u64, len as u64);
+	(i + 1) * n / len != i * n / len
+}
@@ -0,0 +1,389 @@
+use std::{
+	f32::consts::PI,
+	io, thread,
+	time::{Duration, Instant},
+};
+
+use crate::{
+	m8::{
+		self, CHARACTER_CMD, JOYPAD_CMD, KEY_DIR, KEY_DOWN, KEY_LEFT, KEY_RIGHT, KEY_UP,
+		RECTANGLE_CMD, WAVEFORM_CMD,
+	},
+	slip,
+	transport::{Memory, Transport},
+};
+
+const CHAR_WIDTH: u16 = 8;
+const LINE_HEIGHT: u16 = 10;
+const COLS: u16 = 8;
+const ROWS: u16 = 16;
+const GRID_X: u16 = 32;
+const GRID_Y: u16 = 40;
+const TICK: Duration = Duration::from_millis(16);
+const BACKGROUND: (u8, u8, u8) = (0, 0, 0);
+const TEXT: (u8, u8, u8) = (0x8c, 0x8c, 0xba);
+const VALUE: (u8, u8, u8) = (0xfa, 0xfa, 0xfa);
+const TITLE: (u8, u8, u8) = (0x32, 0xec, 0xff);
+const CURSOR: (u8, u8, u8) = (0x32, 0xec, 0xff);
+const NOTES: [&str; 12] = ["C-", "C#", "D-", "D#", "E-", "F-", "F#", "G-", "G#", "A-", "A#", "B-"];
+
+/// A stand-in for the M8 that speaks the same SLIP protocol.
+///
+/// It draws a small grid with a cursor moved by the direction keys, shows the state of the keys
+/// and the last keyjazz note, and sends a sine waveform while a note is playing.
+pub struct Emulator {
+	enabled: bool,
+	keys: u8,
+	note: Option<(u8, u8)>,
+	cursor: (u16, u16),
+	phase: f32,
+	input: Vec<u8>,
+	output: Vec<u8>,
+}
+
+impl Emulator {
+	pub fn new() -> Self {
+		Self {
+			enabled: false,
+			keys: 0,
+			note: None,
+			cursor: (0, 0),
+			phase: 0.0,
+			input: Vec::new(),
+			output: Vec::new(),
+		}
+	}
+
+	/// Starts an emulator on its own thread, returns the end of the connection for rm8.
+	pub fn start() -> Memory {
+		let (device, host) = Memory::pair("emulator");
+		Self::spawn(device);
+		host
+	}
+
+	/// Runs the emulator on its own thread, on the device end of `transport`.
+	pub fn spawn<T: Transport + Send + 'static>(transport: T) -> thread::JoinHandle<()> {
+		let mut transport = transport;
+		thread::spawn(move || {
+			let mut emulator = Emulator::new();
+			let mut ticks = Instant::now();
+			while emulator.step(&mut transport).is_ok() {
+				if ticks.elapsed() >= TICK {
+					ticks = Instant::now();
+					emulator.tick();
+				}
+				thread::sleep(Duration::from_millis(1));
+			}
+		})
+	}
+
+	/// Handles what rm8 sent on the device end of `transport` and writes back the frames
+	/// produced since the last step, without waiting for either. Fails once the transport is
+	/// closed.
+	pub fn step<T: Transport>(&mut self, transport: &mut T) -> io::Result<()> {
+		let mut buf = [0; 256];
+		match transport.read(&mut buf) {
+			Ok(n) => self.receive(&buf[..n]),
+			Err(e)
+				if e.kind() == io::ErrorKind::TimedOut || e.kind() == io::ErrorKind::WouldBlock => {}
+			Err(e) => return Err(e),
+		}
+		let output = self.take_output();
+		if !output.is_empty() {
+			transport.write(&output)?;
+		}
+		Ok(())
+	}
+
+	/// Handles the bytes of the `E`, `R`, `D`, `C` and `K` commands sent by rm8.
+	pub fn receive(&mut self, bytes: &[u8]) {
+		self.input.extend_from_slice(bytes);
+		loop {
+			let consumed = match self.input.first() {
+				Some(b'E') => {
+					self.enabled = true;
+					1
+				}
+				Some(b'R') => {
+					self.redraw();
+					1
+				}
+				Some(b'D') => {
+					self.enabled = false;
+					1
+				}
+				Some(b'C') if self.input.len() >= 2 => {
+					self.set_keys(self.input[1]);
+					2
+				}
+				Some(b'K') if self.input.len() >= 2 && self.input[1] == 255 => {
+					self.set_note(None);
+					2
+				}
+				Some(b'K') if self.input.len() >= 3 => {
+					self.set_note(Some((self.input[1], self.input[2])));
+					3
+				}
+				Some(b'C') | Some(b'K') | None => break,
+				Some(_) => 1,
+			};
+			self.input.drain(..consumed);
+		}
+	}
+
+	/// Advances the emulator by one frame.
+	pub fn tick(&mut self) {
+		if !self.enabled || self.note.is_none() {
+			return;
+		}
+		let mut data = [0u8; m8::SCREEN_WIDTH as usize];
+		let amplitude = (m8::WAVEFORM_HEIGHT / 2 - 1) as f32;
+		let velocity = self.note.map(|(_, v)| v).unwrap_or(0) as f32 / 127.0;
+		for (i, p) in data.iter_mut().enumerate() {
+			let y = (self.phase + i as f32 * PI / 20.0).sin() * amplitude * velocity;
+			*p = (amplitude + y) as u8;
+		}
+		self.phase += PI / 8.0;
+		self.waveform(TITLE, &data);
+	}
+
+	/// Returns the SLIP encoded frames produced since the last call.
+	pub fn take_output(&mut self) -> Vec<u8> {
+		std::mem::take(&mut self.output)
+	}
+
+	fn set_keys(&mut self, keys: u8) {
+		let pressed = keys & !self.keys;
+		self.keys = keys;
+		if !self.enabled {
+			return;
+		}
+		self.frame(&[JOYPAD_CMD, keys, 0]);
+		if pressed & KEY_DIR != 0 {
+			self.draw_cell(self.cursor, false);
+			if pressed & KEY_UP != 0 && self.cursor.1 > 0 {
+				self.cursor.1 -= 1;
+			}
+			if pressed & KEY_DOWN != 0 && self.cursor.1 + 1 < ROWS {
+				self.cursor.1 += 1;
+			}
+			if pressed & KEY_LEFT != 0 && self.cursor.0 > 0 {
+				self.cursor.0 -= 1;
+			}
+			if pressed & KEY_RIGHT != 0 && self.cursor.0 + 1 < COLS {
+				self.cursor.0 += 1;
+			}
+			self.draw_cell(self.cursor, true);
+		}
+		self.draw_status();
+	}
+
+	fn set_note(&mut self, note: Option<(u8, u8)>) {
+		self.note = note;
+		if !self.enabled {
+			return;
+		}
+		if note.is_none() {
+			self.waveform(TITLE, &[]);
+		}
+		self.draw_status();
+	}
+
+	fn redraw(&mut self) {
+		if !self.enabled {
+			return;
+		}
+		let (w, h) = (m8::SCREEN_WIDTH as u16, m8::SCREEN_HEIGHT as u16);
+		self.rectangle(0, 0, w, h, BACKGROUND);
+		self.text(0, 1, "RM8 EMULATOR", TITLE);
+		for row in 0..ROWS {
+			self.text(0, row + 4, &format!("{:02X}", row), TEXT);
+			for col in 0..COLS {
+				self.draw_cell((col, row), (col, row) == self.cursor);
+			}
+		}
+		self.draw_status();
+	}
+
+	fn draw_cell(&mut self, (col, row): (u16, u16), selected: bool) {
+		let (x, y) = (GRID_X + col * CHAR_WIDTH * 3, GRID_Y + row * LINE_HEIGHT);
+		let bg = if selected { CURSOR } else { BACKGROUND };
+		let fg = if selected { BACKGROUND } else { VALUE };
+		self.rectangle(x - 1, y + 2, CHAR_WIDTH * 2, LINE_HEIGHT - 1, bg);
+		self.character(b'-', x, y, fg, fg);
+		self.character(b'-', x + CHAR_WIDTH, y, fg, fg);
+	}
+
+	fn draw_status(&mut self) {
+		let keys = [
+			(m8::KEY_LEFT, 'L'),
+			(m8::KEY_UP, 'U'),
+			(m8::KEY_DOWN, 'D'),
+			(m8::KEY_SHIFT, 'S'),
+			(m8::KEY_PLAY, 'P'),
+			(m8::KEY_RIGHT, 'R'),
+			(m8::KEY_OPTION, 'O'),
+			(m8::KEY_EDIT, 'E'),
+		];
+		let keys: String =
+			keys.iter().map(|&(mask, c)| if self.keys & mask != 0 { c } else { '-' }).collect();
+		let note = match self.note {
+			Some((note, velocity)) => {
+				format!("{}{:X} {:02X}", NOTES[note as usize % 12], note / 12, velocity)
+			}
+			None => "--- --".to_string(),
+		};
+		self.text(0, 21, "KEYS", TEXT);
+		self.text(5, 21, &keys, VALUE);
+		self.text(16, 21, "NOTE", TEXT);
+		self.text(21, 21, &note, VALUE);
+	}
+
+	fn text(&mut self, col: u16, row: u16, s: &str, fg: (u8, u8, u8)) {
+		let y = row * LINE_HEIGHT;
+		self.rectangle(
+			col * CHAR_WIDTH,
+			y + 2,
+			s.len() as u16 * CHAR_WIDTH,
+			LINE_HEIGHT,
+			BACKGROUND,
+		);
+		for (i, c) in s.bytes().enumerate() {
+			self.character(c, (col + i as u16) * CHAR_WIDTH, y, fg, fg);
+		}
+	}
+
+	fn character(&mut self, c: u8, x: u16, y: u16, fg: (u8, u8, u8), bg: (u8, u8, u8)) {
+		let mut frame = vec![CHARACTER_CMD, c];
+		frame.extend_from_slice(&x.to_le_bytes());
+		frame.extend_from_slice(&y.to_le_bytes());
+		frame.extend_from_slice(&[fg.0, fg.1, fg.2, bg.0, bg.1, bg.2]);
+		self.frame(&frame);
+	}
+
+	fn rectangle(&mut self, x: u16, y: u16, w: u16, h: u16, bg: (u8, u8, u8)) {
+		let mut frame = vec![RECTANGLE_CMD];
+		for v in [x, y, w, h] {
+			frame.extend_from_slice(&v.to_le_bytes());
+		}
+		frame.extend_from_slice(&[bg.0, bg.1, bg.2]);
+		self.frame(&frame);
+	}
+
+	fn waveform(&mut self, fg: (u8, u8, u8), data: &[u8]) {
+		let mut frame = vec![WAVEFORM_CMD, fg.0, fg.1, fg.2];
+		frame.extend_from_slice(data);
+		self.frame(&frame);
+	}
+
+	fn frame(&mut self, frame: &[u8]) {
+		slip::encode(frame, &mut self.output);
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use sdl2::keyboard::{Keycode, Mod};
+	use std::{
+		cell::RefCell,
+		rc::Rc,
+		sync::{atomic::AtomicBool, Arc},
+	};
+
+	use super::*;
+	use crate::{
+		app::App,
+		config::{AppConfig, Config},
+		m8::{Command, M8},
+	};
+
+	/// An emulator run by the test, on the device end of a connection.
+	struct Device {
+		emulator: Emulator,
+		transport: Memory,
+	}
+
+	impl Device {
+		fn new(transport: Memory) -> Self {
+			Self { emulator: Emulator::new(), transport }
+		}
+
+		/// Lets the emulator handle what the M8 sent, then reads the M8 until a frame matches.
+		fn step_until(&mut self, m8: &mut M8, matches: impl Fn(&Command<'_>) -> bool) -> bool {
+			self.emulator.step(&mut self.transport).unwrap();
+			while let Some(ref command) = m8.read().unwrap() {
+				if matches(command) {
+					return true;
+				}
+			}
+			false
+		}
+	}
+
+	fn connect() -> (M8, Device) {
+		let (device, host) = Memory::pair("emulator");
+		let mut m8 = M8::with_transport(Box::new(host));
+		m8.enable_and_reset_display().unwrap();
+		(m8, Device::new(device))
+	}
+
+	#[test]
+	fn draws_its_screen_once_enabled() {
+		let (mut m8, mut device) = connect();
+		assert!(device.step_until(&mut m8, |c| matches!(c, Command::Character(b'R', ..))));
+		assert!(m8.link_stats().frames > 0);
+		assert_eq!(m8.link_stats().malformed, 0);
+	}
+
+	#[test]
+	fn gets_the_keys_and_the_notes_of_the_app() {
+		let mut app = App::new(Arc::new(AtomicBool::new(true)));
+		// the keys are acted on at once
+		let app_config = AppConfig { key_sensibility: 0, ..AppConfig::default() };
+		*app.config_mut() = Config { app: app_config, ..Config::default() };
+		let (mut m8, mut device) = connect();
+		assert!(device.step_until(&mut m8, |c| matches!(c, Command::Rectangle(..))));
+
+		app.handle_key(&mut m8, Keycode::Up, Mod::NOMOD, false);
+		assert!(m8.keys.changed());
+		m8.send_keys().unwrap();
+		assert!(device.step_until(&mut m8, |c| matches!(c, Command::Joypad(m8::KEY_UP))));
+		app.handle_key(&mut m8, Keycode::Up, Mod::NOMOD, true);
+		m8.send_keys().unwrap();
+		assert!(device.step_until(&mut m8, |c| matches!(c, Command::Joypad(0))));
+
+		app.handle_key(&mut m8, Keycode::Return, Mod::NOMOD, false);
+		app.process_key(&mut m8);
+		assert!(*m8.keyjazz);
+		m8.set_note(2);
+		m8.send_keyjazz().unwrap();
+		let waveform =
+			|c: &Command<'_>| matches!(c, Command::Waveform(_, data) if !data.is_empty());
+		assert!(!device.step_until(&mut m8, waveform));
+		device.emulator.tick();
+		assert!(device.step_until(&mut m8, waveform));
+	}
+
+	#[test]
+	fn reconnects_through_the_injected_transport() {
+		let (mut lost, host) = Memory::pair("lost");
+		let mut m8 = M8::with_transport(Box::new(host));
+		m8.set_reconnect(true);
+		let reopened = Rc::new(RefCell::new(None));
+		let device = reopened.clone();
+		m8.set_reopen(move || {
+			let (device, host) = Memory::pair("emulator");
+			*reopened.borrow_mut() = Some(device);
+			Ok(Box::new(host))
+		});
+		lost.close().unwrap();
+		assert!(m8.read().unwrap().is_none());
+		assert!(m8.disconnected());
+		// the next read opens the transport again
+		assert!(m8.read().unwrap().is_none());
+		assert!(!m8.disconnected());
+		let mut device = Device::new(device.borrow_mut().take().unwrap());
+		assert!(device.step_until(&mut m8, |c| matches!(c, Command::Character(..))));
+		assert_eq!(m8.device_name().as_deref(), Some("mem:emulator"));
+	}
+}
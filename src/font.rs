@@ -31,7 +31,24 @@ pub const CHARS_BY_ROW: i32 = 16;
 pub const CHARS_BY_COL: i32 = 8;
 pub const CHAR_WIDTH: i32 = WIDTH / CHARS_BY_ROW;
 pub const CHAR_HEIGHT: i32 = HEIGHT / CHARS_BY_COL;
-const DATA: &[u8] = &[
+
+/// Size of the characters of the M8 and their offset from the position it sends.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Glyphs {
+	pub width: i32,
+	pub height: i32,
+	pub offset_y: i32,
+}
+
+impl Glyphs {
+	/// The small font of the M8, the one of the bitmap.
+	pub const SMALL: Self = Self { width: CHAR_WIDTH, height: CHAR_HEIGHT, offset_y: 3 };
+	/// The large font of the M8, drawn with the glyphs of the bitmap stretched to its cells.
+	pub const LARGE: Self = Self { width: CHAR_WIDTH, height: 10, offset_y: 1 };
+}
+/// The glyphs, one bit per pixel, 8 pixels per byte from the least significant bit, a set bit
+/// being transparent.
+pub const DATA: &[u8] = &[
 	0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
 	0xff, 0xe0, 0xe0, 0xe0, 0xe0, 0xe0, 0xe0, 0xe0, 0xe0, 0xe0, 0xe0, 0xe0, 0xe0, 0xe0, 0xe0, 0xe0,
 	0xff, 0xee, 0xee, 0xee, 0xe6, 0xfe, 0xee, 0xe6, 0xfe, 0xee, 0xe6, 0xfe, 0xee, 0xe6, 0xfe, 0xee,
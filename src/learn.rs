@@ -0,0 +1,81 @@
+use crate::{
+	midi::Control,
+	nav::{Input, Item, Navigation},
+};
+
+/// Binds the notes and the controllers of a MIDI device to the items of a page: all of them in
+/// turn, or the one under the cursor.
+pub struct Learn {
+	pos: usize,
+	end: usize,
+	one: bool,
+	cursor: usize,
+	controls: Vec<Option<Control>>,
+}
+
+impl Learn {
+	pub fn new(menu: &mut Navigation) -> Self {
+		Self::start(menu, 0, false)
+	}
+
+	pub fn one(menu: &mut Navigation) -> Self {
+		let pos = menu.page().cursor_item();
+		Self::start(menu, pos, true)
+	}
+
+	fn start(menu: &mut Navigation, pos: usize, one: bool) -> Self {
+		let cursor = menu.page().cursor_item();
+		let mut controls = Vec::new();
+		for (i, item) in menu.page_mut().items_mut().enumerate() {
+			if let Item::Input(_, Input::Midi(m)) = item {
+				if i == pos {
+					m.focus();
+				}
+				controls.push(m.value());
+			}
+		}
+		let end = if one { pos + 1 } else { controls.len() };
+		menu.page_mut().set_cursor(pos);
+		menu.dirty();
+		Self { pos, end, one, cursor, controls }
+	}
+
+	pub fn learn(&mut self, menu: &mut Navigation, control: Control) -> bool {
+		let taken = self
+			.controls
+			.iter()
+			.enumerate()
+			.any(|(i, c)| i != self.pos && (self.one || i < self.pos) && *c == Some(control));
+		{
+			let mut items = menu.page_mut().items_mut().skip(self.pos);
+			if let Some(Item::Input(_, Input::Midi(m))) = items.next() {
+				if taken {
+					m.exists();
+				} else {
+					self.controls[self.pos] = Some(control);
+					m.unfocus();
+					m.set_value(Some(control));
+					self.pos += 1;
+					if self.pos < self.end {
+						if let Some(Item::Input(_, Input::Midi(m))) = items.next() {
+							m.focus();
+						}
+					}
+				}
+			}
+		}
+		menu.page_mut().set_cursor(self.pos);
+		menu.dirty();
+		self.pos >= self.end
+	}
+
+	pub fn abort(&mut self, menu: &mut Navigation) {
+		for item in menu.page_mut().items_mut() {
+			if let Item::Input(_, Input::Midi(m)) = item {
+				m.unfocus();
+			}
+		}
+		menu.page_mut().set_cursor(self.cursor);
+		menu.dirty();
+	}
+}
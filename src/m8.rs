@@ -1,21 +1,37 @@
-use serialport::{available_ports, ErrorKind, SerialPort, SerialPortType};
-use std::{io, time::Duration};
+use serialport::{available_ports, ErrorKind, SerialPortType};
+use std::{
+	io,
+	path::{Path, PathBuf},
+	time::{Duration, Instant},
+};
+
+use sdl2::AudioSubsystem;
 
 use crate::{
 	audio::{self, Audio},
+	config::{AudioConfig, AudioFxConfig, ScreenConfig},
+	font::Glyphs,
+	record::Recorder,
+	server::Server,
 	slip::Slip,
+	transport::{self, Serial, Transport},
 	value::Value,
 };
 
 const VENDOR_ID: u16 = 0x16c0;
 const PRODUCT_ID: u16 = 0x048a;
-const JOYPYAD_CMD: u8 = 0xfb;
-const WAVEFORM_CMD: u8 = 0xfc;
-const CHARACTER_CMD: u8 = 0xfd;
-const RECTANGLE_CMD: u8 = 0xfe;
+pub(crate) const JOYPAD_CMD: u8 = 0xfb;
+pub(crate) const WAVEFORM_CMD: u8 = 0xfc;
+pub(crate) const CHARACTER_CMD: u8 = 0xfd;
+pub(crate) const RECTANGLE_CMD: u8 = 0xfe;
+pub(crate) const SYSTEM_INFO_CMD: u8 = 0xff;
 pub const SCREEN_WIDTH: u32 = 320;
 pub const SCREEN_HEIGHT: u32 = 240;
 pub const WAVEFORM_HEIGHT: u32 = 22;
+const MODEL_02_WIDTH: u32 = 480;
+const MODEL_02_HEIGHT: u32 = 320;
+/// Longest frame: a waveform as wide as the widest screen.
+const MAX_FRAME: usize = 4 + MODEL_02_WIDTH as usize;
 const MIN_OCTAVE: u8 = 0;
 const MAX_OCTAVE: u8 = 10;
 const MIN_VELOCITY: u8 = 0;
@@ -30,50 +46,195 @@ pub const KEY_UP: u8 = 1 << 6;
 pub const KEY_LEFT: u8 = 1 << 7;
 pub const KEY_DIR: u8 = KEY_UP | KEY_DOWN | KEY_LEFT | KEY_RIGHT;
 
+/// Opens a new connection to the M8, see `M8::set_reopen`.
+type Reopen = Box<dyn FnMut() -> io::Result<Box<dyn Transport>>>;
+
 pub enum Command<'a> {
 	#[allow(dead_code)]
 	Joypad(u8),
 	Waveform((u8, u8, u8), &'a [u8]),
 	Character(u8, u16, u16, (u8, u8, u8), (u8, u8, u8)),
 	Rectangle(u16, u16, u16, u16, (u8, u8, u8)),
+	SystemInfo(SystemInfo),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Model {
+	Headless,
+	Beta,
+	Production,
+	Model02,
+}
+
+impl Model {
+	fn from_u8(model: u8) -> Option<Self> {
+		match model {
+			0 => Some(Model::Headless),
+			1 => Some(Model::Beta),
+			2 => Some(Model::Production),
+			3 => Some(Model::Model02),
+			_ => None,
+		}
+	}
+
+	pub fn screen_size(&self) -> (u32, u32) {
+		match self {
+			Model::Model02 => (MODEL_02_WIDTH, MODEL_02_HEIGHT),
+			_ => (SCREEN_WIDTH, SCREEN_HEIGHT),
+		}
+	}
+}
+
+impl std::fmt::Display for Model {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
+			Model::Headless => write!(f, "headless"),
+			Model::Beta => write!(f, "beta"),
+			Model::Production => write!(f, "Model:01"),
+			Model::Model02 => write!(f, "Model:02"),
+		}
+	}
+}
+
+/// What the M8 tells about itself when its display is enabled, by the firmwares that have it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SystemInfo {
+	pub model: Model,
+	pub firmware: (u8, u8, u8),
+	pub large_font: bool,
+}
+
+impl std::fmt::Display for SystemInfo {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		let (major, minor, patch) = self.firmware;
+		write!(f, "M8 {}, firmware {}.{}.{}", self.model, major, minor, patch)?;
+		if self.large_font {
+			write!(f, ", large font")?;
+		}
+		Ok(())
+	}
+}
+
+/// Geometry of the display of an M8.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Screen {
+	pub width: u32,
+	pub height: u32,
+	/// Height of the area cleared before drawing a waveform.
+	pub waveform_height: u32,
+	/// The font the M8 draws its characters with.
+	pub glyphs: Glyphs,
+}
+
+impl Default for Screen {
+	fn default() -> Self {
+		Self {
+			width: SCREEN_WIDTH,
+			height: SCREEN_HEIGHT,
+			waveform_height: WAVEFORM_HEIGHT,
+			glyphs: Glyphs::SMALL,
+		}
+	}
+}
+
+/// State of the link with the M8 and counters of what went through it, see `M8::link_stats`.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct LinkStats {
+	/// Bytes read from the M8.
+	pub bytes: u64,
+	/// Frames decoded, valid or not.
+	pub frames: u64,
+	/// Frames dropped by the SLIP decoder.
+	pub malformed: u64,
+	/// Frames dropped because they were too long.
+	pub overflows: u64,
+	/// Frames whose length does not match their command.
+	pub invalid: u64,
+	/// Frames starting with an unknown command byte.
+	pub unknown: u64,
+	pub joypads: u64,
+	pub waveforms: u64,
+	pub characters: u64,
+	pub rectangles: u64,
+	/// `C` commands sent to the M8.
+	pub keys_sent: u64,
+	/// `K` commands sent to the M8.
+	pub notes_sent: u64,
+	/// Time elapsed since the last frame, if any.
+	pub idle: Option<Duration>,
+	pub lost: bool,
+	pub reconnect: bool,
+	/// What the M8 told about itself, if its firmware does.
+	pub info: Option<SystemInfo>,
 }
 
 pub struct M8 {
-	port: Box<dyn SerialPort>,
-	buf: [u8; 324],
+	transport: Box<dyn Transport>,
+	/// Opens a new connection when the link is lost, rather than opening the device again.
+	reopen: Option<Reopen>,
+	buf: [u8; MAX_FRAME],
 	reconnect: bool,
 	lost: bool,
 	slip: Slip<1024>,
 	pub keyjazz: Value<bool>,
 	pub note: Value<u8>,
+	/// Velocity of the note when it has its own, as from a MIDI keyboard.
+	note_velocity: Option<u8>,
 	pub octave: Value<u8>,
 	pub velocity: Value<u8>,
 	pub keys: Value<u8>,
 	audio: Option<Audio>,
-	loopback: bool,
+	recorder: Option<Recorder>,
+	server: Option<Server>,
+	link: LinkStats,
+	last_frame: Option<Instant>,
+	info: Option<SystemInfo>,
+	screen: Screen,
+	/// Size of the screen forced by the config, whatever the model.
+	screen_config: Option<ScreenConfig>,
+	/// Color of the last rectangle, for the short rectangle frames that do not repeat it.
+	color: (u8, u8, u8),
 }
 
 impl Drop for M8 {
 	fn drop(&mut self) {
 		let _ = self.disconnect();
+		let _ = self.transport.close();
 	}
 }
 
 impl M8 {
-	pub fn list_capture_devices() -> Result<Vec<String>, String> {
-		// TODO:
-		Ok(vec!["M8 Analog Stereo".to_string()])
-	}
-
 	pub fn capture_device_name(&self) -> Option<String> {
 		self.audio.as_ref().map(|a| a.name())
 	}
 
+	pub fn audio_samples(&mut self, out: &mut Vec<i16>) -> Option<i32> {
+		self.audio.as_mut().map(|a| a.samples(out))
+	}
+
+	pub fn set_audio_fx(&mut self, config: &AudioFxConfig) {
+		if let Some(ref mut audio) = self.audio {
+			audio.set_fx(config);
+		}
+	}
+
+	pub fn audio_stats(&self) -> Option<audio::Stats> {
+		self.audio.as_ref().map(|a| a.stats())
+	}
+
+	pub fn playback_device_name(&self) -> Option<String> {
+		self.audio.as_ref().and_then(|a| a.playback_name())
+	}
+
 	pub fn connect_audio(&mut self, audio: Audio) {
 		let mut audio = audio;
 		audio.resume();
 		self.audio.replace(audio);
-		self.loopback = true;
+	}
+
+	/// Disconnects the audio loopback, so that it can be connected to another M8.
+	pub fn take_audio(&mut self) -> Option<Audio> {
+		self.audio.take()
 	}
 
 	pub fn toggle_audio(&mut self) {
@@ -82,37 +243,73 @@ impl M8 {
 		}
 	}
 
-	pub fn reopen_audio(&mut self, name: String) -> Result<(), String> {
-		if !self.loopback {
+	pub fn record_audio(&mut self, file: &Path) -> Result<(), String> {
+		match self.audio {
+			Some(ref mut audio) => audio.start_recording(file),
+			None => Err("audio loopback is disabled".to_string()),
+		}
+	}
+
+	pub fn stop_audio_recording(&mut self) -> Option<Result<PathBuf, String>> {
+		self.audio.as_mut().and_then(|audio| audio.stop_recording())
+	}
+
+	pub fn audio_recording(&self) -> bool {
+		self.audio.as_ref().map(|audio| audio.recording()).unwrap_or(false)
+	}
+
+	/// Applies the audio config: opens, reopens or closes the audio loopback.
+	pub fn configure_audio(
+		&mut self,
+		subsystem: &AudioSubsystem,
+		config: &AudioConfig,
+	) -> Result<(), String> {
+		if !config.enabled {
+			self.audio = None;
 			return Ok(());
 		}
-		if let Some(ref mut audio) = self.audio {
-			audio.reopen(todo!(), name, todo!())
-		} else {
-			self.audio.replace(audio::Audio::open(todo!(), Some(name), todo!())?);
-			Ok(())
+		match self.audio.take() {
+			Some(audio) => self.audio = Some(audio.reopen(config)?),
+			None => self.connect_audio(Audio::open(subsystem, config)?),
+		}
+		Ok(())
+	}
+
+	/// Returns true if applying `config` closes the audio devices in use.
+	pub fn closes_audio(&self, config: &AudioConfig) -> bool {
+		self.audio.as_ref().is_some_and(|audio| audio.config() != config)
+	}
+
+	pub fn with_transport(transport: Box<dyn Transport>) -> Self {
+		Self {
+			audio: None,
+			transport,
+			reopen: None,
+			reconnect: false,
+			lost: false,
+			buf: [0; MAX_FRAME],
+			slip: Slip::new(),
+			keyjazz: Value::new(false),
+			note: Value::new(255),
+			note_velocity: None,
+			octave: Value::new(3),
+			velocity: Value::new(100),
+			keys: Value::new(0),
+			recorder: None,
+			server: None,
+			link: LinkStats::default(),
+			last_frame: None,
+			info: None,
+			screen: Screen::default(),
+			screen_config: None,
+			color: (0, 0, 0),
 		}
 	}
 
 	fn open_serial(p: &serialport::SerialPortInfo) -> serialport::Result<Self> {
 		if let SerialPortType::UsbPort(ref info) = p.port_type {
 			if info.vid == VENDOR_ID && info.pid == PRODUCT_ID {
-				return Ok(Self {
-					audio: None,
-					loopback: false,
-					port: serialport::new(&p.port_name, 115200)
-						.timeout(Duration::from_millis(1))
-						.open()?,
-					reconnect: false,
-					lost: false,
-					buf: [0; 324],
-					slip: Slip::new(),
-					keyjazz: Value::new(false),
-					note: Value::new(255),
-					octave: Value::new(3),
-					velocity: Value::new(100),
-					keys: Value::new(0),
-				});
+				return Ok(Self::with_transport(Box::new(Serial::open(&p.port_name)?)));
 			}
 		}
 		Err(serialport::Error {
@@ -122,22 +319,64 @@ impl M8 {
 	}
 
 	pub fn device_name(&self) -> Option<String> {
-		self.port.name()
+		self.transport.name()
+	}
+
+	pub fn set_recorder(&mut self, recorder: Recorder) {
+		self.recorder.replace(recorder);
+	}
+
+	pub fn set_server(&mut self, server: Server) {
+		self.server.replace(server);
+	}
+
+	/// Forwards the keys, notes and display resets sent by the remote clients to the M8.
+	pub fn serve(&mut self) -> Result<(), String> {
+		let input = match self.server {
+			Some(ref server) => server.input(),
+			None => return Ok(()),
+		};
+		for command in input {
+			// the clients do not get to enable or disconnect the M8
+			if let Some(b'C' | b'K' | b'R') = command.first() {
+				self.write(&command)?;
+			}
+		}
+		Ok(())
 	}
 
 	pub fn set_reconnect(&mut self, reconnect: bool) {
 		self.reconnect = reconnect;
 	}
 
+	/// Reconnects through `reopen`, as for the emulator, rather than by opening the device again.
+	pub fn set_reopen<F>(&mut self, reopen: F)
+	where
+		F: FnMut() -> io::Result<Box<dyn Transport>> + 'static,
+	{
+		self.reopen = Some(Box::new(reopen));
+	}
+
 	pub fn disconnected(&self) -> bool {
 		self.lost
 	}
 
 	fn try_reconnect(&mut self) -> Result<(), String> {
 		if self.reconnect && self.lost {
-			if let Ok(mut new_self) = M8::detect() {
-				std::mem::swap(&mut self.port, &mut new_self.port);
+			// only the same device: another M8 may belong to another instance, and a remote one
+			// must not be swapped for a local one
+			let name = self.device_name();
+			let new_self = match (self.reopen.as_mut(), name) {
+				(Some(reopen), _) => reopen().map(M8::with_transport),
+				(None, Some(name)) => M8::open(name),
+				(None, None) => M8::detect(),
+			};
+			if let Ok(mut new_self) = new_self {
+				std::mem::swap(&mut self.transport, &mut new_self.transport);
 				self.lost = false;
+				// maybe not the same model, it tells again once its display is enabled
+				self.info = None;
+				self.screen = self.screen_for(None);
 				self.reset(true)?;
 				if *self.keyjazz {
 					self.keyjazz.set_changed();
@@ -147,17 +386,18 @@ impl M8 {
 		Ok(())
 	}
 
-	pub fn open<T: AsRef<str>>(device: T) -> serialport::Result<Self> {
+	pub fn open<T: AsRef<str>>(device: T) -> io::Result<Self> {
+		let device = device.as_ref();
+		if device.starts_with("tcp:") || device.starts_with("pipe:") {
+			return Ok(Self::with_transport(transport::open(device)?));
+		}
 		for p in available_ports()? {
-			if p.port_name != device.as_ref() {
+			if p.port_name != device {
 				continue;
 			}
-			return Self::open_serial(&p);
+			return Ok(Self::open_serial(&p)?);
 		}
-		Err(serialport::Error {
-			kind: ErrorKind::NoDevice,
-			description: "Device not found".to_string(),
-		})
+		Err(io::Error::new(io::ErrorKind::NotFound, "Device not found"))
 	}
 
 	pub fn list_ports() -> serialport::Result<Vec<String>> {
@@ -172,58 +412,157 @@ impl M8 {
 		Ok(v)
 	}
 
-	pub fn detect() -> serialport::Result<Self> {
+	pub fn detect() -> io::Result<Self> {
 		for p in available_ports()? {
 			if let Ok(p) = Self::open_serial(&p) {
 				return Ok(p);
 			}
 		}
-		Err(serialport::Error {
-			kind: ErrorKind::NoDevice,
-			description: "No port found".to_string(),
-		})
+		Err(io::Error::new(io::ErrorKind::NotFound, "No port found"))
 	}
 
 	pub fn read(&mut self) -> Result<Option<Command<'_>>, String> {
 		self.try_reconnect()?;
-		match self.slip.read(&mut self.port, &mut self.buf) {
-			Ok(Some(bytes)) if !bytes.is_empty() => match bytes[0] {
-				JOYPYAD_CMD if bytes.len() == 3 => Ok(None),
-				JOYPYAD_CMD => Err("invalid joypad command".to_string()),
-				WAVEFORM_CMD if bytes.len() == 4 || bytes.len() == 324 => {
-					Ok(Some(Command::Waveform((bytes[1], bytes[2], bytes[3]), &bytes[4..])))
+		let len = loop {
+			match self.slip.read(self.transport.as_mut(), &mut self.buf) {
+				Ok(Some(bytes)) => {
+					self.last_frame = Some(Instant::now());
+					if let Some(ref mut recorder) = self.recorder {
+						if let Err(e) = recorder.record(bytes) {
+							self.recorder = None;
+							return Err(format!("record failed: {}", e));
+						}
+					}
+					if let Some(ref mut server) = self.server {
+						server.broadcast(bytes);
+					}
+					match (bytes[0], bytes.len()) {
+						(JOYPAD_CMD, 3)
+						| (WAVEFORM_CMD, 4)
+						| (CHARACTER_CMD, 12)
+						| (RECTANGLE_CMD, 5 | 8 | 9 | 12)
+						| (SYSTEM_INFO_CMD, 6) => break bytes.len(),
+						(WAVEFORM_CMD, n)
+							if n == 4 + SCREEN_WIDTH as usize
+								|| n == 4 + MODEL_02_WIDTH as usize =>
+						{
+							break n
+						}
+						(
+							JOYPAD_CMD | WAVEFORM_CMD | CHARACTER_CMD | RECTANGLE_CMD
+							| SYSTEM_INFO_CMD,
+							_,
+						) => self.link.invalid += 1,
+						_ => self.link.unknown += 1,
+					}
+				}
+				Ok(None) => return Ok(None),
+				Err(e) if e.kind() == io::ErrorKind::BrokenPipe => {
+					self.lost = true;
+					return Ok(None);
 				}
-				WAVEFORM_CMD => Err("invalid waveform command".to_string()),
-				CHARACTER_CMD if bytes.len() == 12 => Ok(Some(Command::Character(
-					bytes[1],
-					read16(&bytes[2..4]),
-					read16(&bytes[4..6]),
-					(bytes[6], bytes[7], bytes[8]),
-					(bytes[9], bytes[10], bytes[11]),
-				))),
-				CHARACTER_CMD => Err("invalid character command".to_string()),
-				RECTANGLE_CMD if bytes.len() == 12 => Ok(Some(Command::Rectangle(
-					read16(&bytes[1..3]),
-					read16(&bytes[3..5]),
-					read16(&bytes[5..7]),
-					read16(&bytes[7..9]),
-					(bytes[9], bytes[10], bytes[11]),
-				))),
-				RECTANGLE_CMD => Err("invalid rectangle command".to_string()),
-				_ => Err(format!("unknown command {:02X}", bytes[0])),
-			},
-			Ok(None) => Ok(None),
-			Ok(_) => Err("empty command".to_string()),
-			Err(e) if e.kind() == io::ErrorKind::BrokenPipe => {
-				self.lost = true;
-				Ok(None)
+				Err(e) => return Err(format!("read failed: {}", e)),
 			}
-			Err(e) => Err(format!("read failed: {}", e)),
+		};
+		let bytes = &self.buf[..len];
+		match bytes[0] {
+			JOYPAD_CMD => self.link.joypads += 1,
+			WAVEFORM_CMD => self.link.waveforms += 1,
+			CHARACTER_CMD => self.link.characters += 1,
+			RECTANGLE_CMD => self.link.rectangles += 1,
+			_ => {}
+		}
+		Ok(Some(match bytes[0] {
+			JOYPAD_CMD => Command::Joypad(bytes[1]),
+			WAVEFORM_CMD => {
+				let data = &bytes[4..];
+				if let Some(max) = data.iter().max() {
+					let height = *max as u32 + 1;
+					self.screen.waveform_height = self.screen.waveform_height.max(height);
+				}
+				Command::Waveform((bytes[1], bytes[2], bytes[3]), data)
+			}
+			CHARACTER_CMD => Command::Character(
+				bytes[1],
+				read16(&bytes[2..4]),
+				read16(&bytes[4..6]),
+				(bytes[6], bytes[7], bytes[8]),
+				(bytes[9], bytes[10], bytes[11]),
+			),
+			RECTANGLE_CMD => {
+				// newer firmwares leave out the size of single pixels and the color when it
+				// does not change
+				let (x, y) = (read16(&bytes[1..3]), read16(&bytes[3..5]));
+				let (w, h) = match len {
+					9 | 12 => (read16(&bytes[5..7]), read16(&bytes[7..9])),
+					_ => (1, 1),
+				};
+				match len {
+					8 => self.color = (bytes[5], bytes[6], bytes[7]),
+					12 => self.color = (bytes[9], bytes[10], bytes[11]),
+					_ => {}
+				}
+				Command::Rectangle(x, y, w, h, self.color)
+			}
+			_ => {
+				let info = SystemInfo {
+					model: Model::from_u8(bytes[1]).unwrap_or(Model::Production),
+					firmware: (bytes[2], bytes[3], bytes[4]),
+					large_font: bytes[5] == 1,
+				};
+				if self.info != Some(info) {
+					eprintln!("{}", info);
+					self.screen = self.screen_for(Some(info));
+					self.info = Some(info);
+				}
+				Command::SystemInfo(info)
+			}
+		}))
+	}
+
+	pub fn screen(&self) -> Screen {
+		self.screen
+	}
+
+	/// Forces the size of the screen rather than following the model of the M8.
+	pub fn set_screen_config(&mut self, config: Option<ScreenConfig>) {
+		self.screen_config = config;
+		self.screen = self.screen_for(self.info);
+	}
+
+	fn screen_for(&self, info: Option<SystemInfo>) -> Screen {
+		let (width, height) = match (self.screen_config, info) {
+			(Some(config), _) => (config.width, config.height),
+			(None, Some(info)) => info.model.screen_size(),
+			(None, None) => (SCREEN_WIDTH, SCREEN_HEIGHT),
+		};
+		let large_font = info.is_some_and(|info| info.large_font);
+		let glyphs = if large_font { Glyphs::LARGE } else { Glyphs::SMALL };
+		Screen { width, height, glyphs, ..Screen::default() }
+	}
+
+	pub fn link_stats(&self) -> LinkStats {
+		let slip = self.slip.stats();
+		LinkStats {
+			bytes: slip.bytes,
+			frames: slip.frames,
+			malformed: slip.malformed,
+			overflows: slip.overflows,
+			idle: self.last_frame.map(|t| t.elapsed()),
+			lost: self.lost,
+			reconnect: self.reconnect,
+			info: self.info,
+			..self.link
 		}
 	}
 
 	fn write(&mut self, buf: &[u8]) -> Result<(), String> {
-		match self.port.write(buf) {
+		match buf.first() {
+			Some(b'C') => self.link.keys_sent += 1,
+			Some(b'K') => self.link.notes_sent += 1,
+			_ => {}
+		}
+		match self.transport.write(buf) {
 			Ok(n) if n != buf.len() => Err("failed to write command".to_string()),
 			Ok(_) => Ok(()),
 			Err(e) if e.kind() == io::ErrorKind::BrokenPipe => Ok(()),
@@ -273,7 +612,7 @@ impl M8 {
 		if *self.note == 255 {
 			self.write(&[b'K', *self.note])
 		} else {
-			self.write(&[b'K', *self.note, *self.velocity])
+			self.write(&[b'K', *self.note, self.note_velocity.unwrap_or(*self.velocity)])
 		}
 	}
 
@@ -302,11 +641,96 @@ impl M8 {
 	}
 
 	pub fn set_note(&mut self, note: u8) {
+		self.note_velocity = None;
 		self.note.set(note + *self.octave * 12)
 	}
+
+	/// Plays a note as it is, with its own velocity if any, as from a MIDI keyboard.
+	pub fn play_note(&mut self, note: u8, velocity: Option<u8>) {
+		self.note_velocity = velocity;
+		self.note.set(note);
+		// the same note played again is sent again
+		self.note.set_changed();
+	}
+
+	/// Stops a note played with `play_note`, unless another one was played since.
+	pub fn stop_note(&mut self, note: u8) {
+		if *self.note == note {
+			self.set_note_off();
+		}
+	}
 }
 
 fn read16(bytes: &[u8]) -> u16 {
 	assert!(bytes.len() == 2);
 	u16::from_le_bytes(bytes.try_into().unwrap())
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::{slip, transport::Memory};
+
+	fn send(device: &mut Memory, frame: &[u8]) {
+		let mut bytes = Vec::new();
+		slip::encode(frame, &mut bytes);
+		device.write(&bytes).unwrap();
+	}
+
+	#[test]
+	fn skips_unknown_and_invalid_frames() {
+		let (mut device, host) = Memory::pair("test");
+		let mut m8 = M8::with_transport(Box::new(host));
+		send(&mut device, &[0x42, 1, 2]);
+		send(&mut device, &[RECTANGLE_CMD, 1, 2, 3]);
+		send(&mut device, &[WAVEFORM_CMD, 1, 2, 3, 4, 5]);
+		send(&mut device, &[JOYPAD_CMD, KEY_PLAY, 0]);
+		assert!(matches!(m8.read(), Ok(Some(Command::Joypad(KEY_PLAY)))));
+		assert!(matches!(m8.read(), Ok(None)));
+		let stats = m8.link_stats();
+		assert_eq!((stats.frames, stats.unknown, stats.invalid), (4, 1, 2));
+		assert_eq!(stats.joypads, 1);
+	}
+
+	#[test]
+	fn resyncs_after_bad_frames() {
+		let (mut device, host) = Memory::pair("test");
+		let mut m8 = M8::with_transport(Box::new(host));
+		// a bad escape, an empty frame and a frame longer than any command
+		device.write(&[RECTANGLE_CMD, 0xdb, 0x01, 0, 0xc0, 0xc0, 0xc0]).unwrap();
+		send(&mut device, &[WAVEFORM_CMD; MAX_FRAME + 1]);
+		send(&mut device, &[RECTANGLE_CMD, 1, 0, 2, 0, 0xc0, 0xdb, 7]);
+		match m8.read() {
+			Ok(Some(Command::Rectangle(1, 2, 1, 1, color))) => assert_eq!(color, (0xc0, 0xdb, 7)),
+			_ => panic!("expected a rectangle"),
+		}
+		assert!(matches!(m8.read(), Ok(None)));
+		let stats = m8.link_stats();
+		assert_eq!((stats.frames, stats.malformed, stats.overflows), (1, 1, 1));
+	}
+
+	#[test]
+	fn reports_a_closed_transport_as_lost() {
+		let (mut device, host) = Memory::pair("test");
+		let mut m8 = M8::with_transport(Box::new(host));
+		device.close().unwrap();
+		assert!(matches!(m8.read(), Ok(None)));
+		assert!(m8.disconnected());
+	}
+
+	#[test]
+	fn follows_the_model_and_the_font_of_the_system_info() {
+		let (mut device, host) = Memory::pair("test");
+		let mut m8 = M8::with_transport(Box::new(host));
+		send(&mut device, &[SYSTEM_INFO_CMD, 3, 5, 0, 1, 1]);
+		match m8.read() {
+			Ok(Some(Command::SystemInfo(info))) => assert!(info.large_font),
+			_ => panic!("expected the system info"),
+		}
+		let screen = m8.screen();
+		assert_eq!((screen.width, screen.height, screen.glyphs), (480, 320, Glyphs::LARGE));
+		send(&mut device, &[SYSTEM_INFO_CMD, 3, 5, 0, 1, 0]);
+		assert!(matches!(m8.read(), Ok(Some(Command::SystemInfo(_)))));
+		assert_eq!(m8.screen().glyphs, Glyphs::SMALL);
+	}
+}
@@ -0,0 +1,132 @@
+use std::time::{Duration, Instant};
+
+use crate::config::{Command, MacroStep};
+
+/// Name of the macro made by recording the input.
+pub const RECORDED: &str = "RECORDED";
+
+/// Turns the commands of the keyboard and the joysticks into the steps of a macro.
+#[derive(Default)]
+pub struct Recorder {
+	steps: Vec<MacroStep>,
+	keys: u8,
+	pressed: bool,
+}
+
+impl Recorder {
+	/// Notes a command, `keys` being the M8 keys once it is applied. The M8 keys held together
+	/// make one step, recorded when the first of them is released.
+	pub fn command(&mut self, cmd: Command, keys: u8, clear: bool) {
+		match cmd {
+			Command::Up
+			| Command::Down
+			| Command::Left
+			| Command::Right
+			| Command::Edit
+			| Command::r#Option
+			| Command::Shift
+			| Command::Play => {
+				if !clear {
+					self.keys = keys;
+					self.pressed = true;
+				} else {
+					self.release();
+				}
+			}
+			// an axis or a hat back to its center releases its direction
+			Command::None => self.release(),
+			Command::Keyjazz
+			| Command::Config
+			| Command::Escape
+			| Command::MacroRecord
+			| Command::MacroPlay => {}
+			_ => {
+				if !clear {
+					self.steps.push(MacroStep::Command(cmd));
+				}
+			}
+		}
+	}
+
+	fn release(&mut self) {
+		if self.pressed {
+			self.steps.push(MacroStep::Keys(self.keys));
+			self.pressed = false;
+		}
+	}
+
+	pub fn finish(self) -> Vec<MacroStep> {
+		self.steps
+	}
+}
+
+/// Plays the steps of a macro, holding each one for the delay then releasing it for as long.
+pub struct Player {
+	steps: Vec<MacroStep>,
+	delay: Duration,
+	pos: usize,
+	release: bool,
+	next: Instant,
+}
+
+impl Player {
+	pub fn new(steps: Vec<MacroStep>, delay: u64) -> Self {
+		Self {
+			steps,
+			delay: Duration::from_millis(delay),
+			pos: 0,
+			release: false,
+			next: Instant::now(),
+		}
+	}
+
+	/// Returns the step to press, or to release if the flag is set, once it is time to.
+	pub fn step(&mut self) -> Option<(MacroStep, bool)> {
+		let now = Instant::now();
+		if self.done() || now < self.next {
+			return None;
+		}
+		let step = (self.steps[self.pos], self.release);
+		if self.release {
+			self.pos += 1;
+		}
+		self.release = !self.release;
+		self.next = now + self.delay;
+		Some(step)
+	}
+
+	pub fn done(&self) -> bool {
+		self.pos >= self.steps.len()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::m8::{KEY_EDIT, KEY_UP};
+
+	#[test]
+	fn records_the_keys_held_together() {
+		let mut recorder = Recorder::default();
+		recorder.command(Command::Edit, KEY_EDIT, false);
+		recorder.command(Command::Up, KEY_EDIT | KEY_UP, false);
+		recorder.command(Command::Up, KEY_EDIT, true);
+		recorder.command(Command::Edit, 0, true);
+		recorder.command(Command::Screenshot, 0, false);
+		let steps = recorder.finish();
+		assert_eq!(
+			steps,
+			[MacroStep::Keys(KEY_EDIT | KEY_UP), MacroStep::Command(Command::Screenshot)]
+		);
+	}
+
+	#[test]
+	fn records_the_directions_of_the_axes() {
+		let mut recorder = Recorder::default();
+		recorder.command(Command::Up, KEY_UP, false);
+		// the axis is back to its center
+		recorder.command(Command::None, 0, true);
+		recorder.command(Command::None, 0, true);
+		assert_eq!(recorder.finish(), [MacroStep::Keys(KEY_UP)]);
+	}
+}
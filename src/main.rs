@@ -1,19 +1,20 @@
 #[macro_use]
 extern crate serde_derive;
 
-use sdl2::{
-	event::Event,
-	keyboard::{Keycode, Mod},
-	pixels::PixelFormatEnum,
-	video,
-};
-use std::sync::{
-	atomic::{self, AtomicBool},
-	Arc,
+use sdl2::{event::Event, keyboard::Keycode, pixels::PixelFormatEnum, video};
+use std::{
+	io,
+	path::Path,
+	sync::{
+		atomic::{self, AtomicBool},
+		Arc,
+	},
 };
 
+mod analyzer;
 mod app;
 mod audio;
+mod capture;
 mod cli;
 mod config;
 mod config_command;
@@ -21,17 +22,32 @@ mod config_font;
 mod config_joystick;
 mod config_keycode;
 mod config_rgb;
+mod cpu;
+mod diagnostics;
 mod draw;
+mod dsp;
+mod effect;
+mod emulator;
 mod font;
+mod learn;
 mod m8;
+mod macros;
 mod menu;
 mod menu_tools;
+mod midi;
 mod nav;
 mod nav_item;
 mod nav_page;
+mod record;
 mod remap;
+mod ring;
+mod server;
 mod slip;
+mod status;
+mod transport;
 mod value;
+mod wav;
+mod web;
 
 use app::App;
 use config::Rgb;
@@ -46,41 +62,69 @@ fn main() -> Result<(), String> {
 		.map_err(|e| e.to_string())?;
 
 	// process command line arguments
-	let mut device: Option<String> = None;
-	let mut config_file: Option<String> = None;
-	let mut capture: Option<String> = None;
-	let mut samples: Option<u16> = None;
-	let mut noaudio = false;
-	if !cli::handle_command_line(
-		app.config_mut(),
-		&mut config_file,
-		&mut device,
-		&mut capture,
-		&mut samples,
-		&mut noaudio,
-	)? {
+	let mut options = cli::Options::default();
+	if !cli::handle_command_line(app.config_mut(), &mut options)? {
 		return Ok(());
 	}
 
-	// detect and connect to M8
-	let mut m8 = match device {
-		Some(dev) => M8::open(dev),
-		None => M8::detect(),
+	// detect and connect to M8s, or replay a recording, or start the emulator
+	let mut replay = match options.replay {
+		Some(ref file) => Some(record::Replay::open(file).map_err(|e| format!("{}: {}", file, e))?),
+		None => None,
+	};
+	let mut m8s = match replay {
+		Some(ref replay) => Ok(vec![M8::with_transport(replay.transport())]),
+		None if options.emulator => {
+			let mut m8 = M8::with_transport(Box::new(emulator::Emulator::start()));
+			// a new emulator takes over when the link is lost
+			m8.set_reopen(|| Ok(Box::new(emulator::Emulator::start())));
+			Ok(vec![m8])
+		}
+		None if options.connect.is_some() => {
+			options.connect.iter().map(|addr| M8::open(format!("tcp:{}", addr))).collect()
+		}
+		None if options.all => match M8::list_ports().map_err(|e| e.to_string())? {
+			ports if ports.is_empty() => {
+				Err(io::Error::new(io::ErrorKind::NotFound, "No M8 found"))
+			}
+			ports => ports.iter().map(M8::open).collect(),
+		},
+		None if !options.devices.is_empty() => options.devices.iter().map(M8::open).collect(),
+		None => M8::detect().map(|m8| vec![m8]),
 	}
 	.map_err(|e| e.to_string())?;
-	m8.set_reconnect(app.config().app.reconnect);
-	m8.enable_and_reset_display()?;
-	m8.keyjazz.set(!app.config().overlap);
-
-	app.build_menu(&m8);
+	// the display stream of the first M8 only is recorded
+	if let Some(ref file) = options.record {
+		m8s[0]
+			.set_recorder(record::Recorder::create(file).map_err(|e| format!("{}: {}", file, e))?);
+	}
+	if options.serve.is_some() || options.web.is_some() {
+		let server = server::Server::new();
+		if let Some(ref addr) = options.serve {
+			server.listen(addr).map_err(|e| format!("{}: {}", addr, e))?;
+		}
+		if let Some(ref addr) = options.web {
+			server
+				.listen_http(addr, app.config().app.font, options.web_input)
+				.map_err(|e| format!("{}: {}", addr, e))?;
+		}
+		m8s[0].set_server(server);
+	}
+	for m8 in m8s.iter_mut() {
+		m8.set_reconnect(app.config().app.reconnect);
+		m8.set_screen_config(app.config().screen);
+		m8.enable_and_reset_display()?;
+		m8.keyjazz.set(!app.config().overlap);
+	}
 
 	let sdl_context = sdl2::init()?;
 	let joystick_subsystem = sdl_context.joystick()?;
 	let video_subsystem = sdl_context.video()?;
 	let audio_subsystem = sdl_context.audio()?;
 	let zoom = app.config().app.zoom;
+	let screen = m8s[0].screen();
 	let mut window = video_subsystem
-		.window("rm8", m8::SCREEN_WIDTH * zoom, m8::SCREEN_HEIGHT * zoom)
+		.window("rm8", screen.width * zoom, screen.height * zoom)
 		.position_centered()
 		.opengl()
 		.resizable()
@@ -92,89 +136,161 @@ fn main() -> Result<(), String> {
 		video::FullscreenType::Off
 	})?;
 
-	if !noaudio {
-		m8.connect_audio(audio::Audio::open(&audio_subsystem, capture, samples)?);
+	let mut audio_config = app.config().audio.clone();
+	if options.samples.is_some() {
+		audio_config.samples = options.samples;
+	}
+	// a remote M8 plays its audio where it is plugged
+	let audio_loopback =
+		!options.noaudio && replay.is_none() && !options.emulator && options.connect.is_none();
+	app.set_audio_loopback(audio_loopback);
+	if audio_config.enabled && audio_loopback {
+		let audio = match options.capture {
+			Some(capture) => audio::Audio::open(
+				&audio_subsystem,
+				&config::AudioConfig { capture: Some(capture), ..audio_config },
+			)?,
+			// a device saved in the config may be gone, look for any M8 then
+			None => audio::Audio::open(&audio_subsystem, &audio_config).or_else(|_| {
+				audio::Audio::open(
+					&audio_subsystem,
+					&config::AudioConfig { capture: None, ..audio_config },
+				)
+			})?,
+		};
+		// the audio loopback follows the focused M8
+		m8s[0].connect_audio(audio);
+		m8s[0].set_audio_fx(&app.config().audio_fx);
+	}
+	if let Some(ref file) = options.record_audio {
+		m8s[0].record_audio(Path::new(file))?;
 	}
 
-	let mut canvas = window.into_canvas().accelerated().build().map_err(|e| e.to_string())?;
-	canvas.set_logical_size(m8::SCREEN_WIDTH, m8::SCREEN_HEIGHT).map_err(|e| e.to_string())?;
+	// the notes of a MIDI keyboard go to the focused M8
+	let midi = match options.midi.as_ref().or(app.config().midi.device.as_ref()) {
+		Some(device) => match midi::MidiInput::open(device) {
+			Ok(midi) => Some(midi),
+			Err(e) => {
+				eprintln!("could not open MIDI device: {}", e);
+				None
+			}
+		},
+		None => None,
+	};
+
+	app.build_menu(&m8s[0], &audio_subsystem);
+
+	// without a GPU, SDL falls back to its software renderer
+	let mut canvas = window.into_canvas().build().map_err(|e| e.to_string())?;
+	app.set_screens(&mut canvas, m8s.len())?;
 
 	let creator = canvas.texture_creator();
-	let mut texture = creator
-		.create_texture_target(PixelFormatEnum::ARGB8888, m8::SCREEN_WIDTH, m8::SCREEN_HEIGHT)
+	let mut filter = app.config().app.filter;
+	sdl2::hint::set("SDL_RENDER_SCALE_QUALITY", filter.hint());
+	let mut overlay = effect::Overlay::new(&creator);
+	let mut textures = m8s
+		.iter()
+		.map(|m8| {
+			let screen = m8.screen();
+			creator.create_texture_target(PixelFormatEnum::ARGB8888, screen.width, screen.height)
+		})
+		.collect::<Result<Vec<_>, _>>()
 		.map_err(|e| e.to_string())?;
+	let mut damages = vec![draw::Damage::default(); m8s.len()];
 
 	let mut font = font::init(&creator)?;
 
 	let mut event_pump = sdl_context.event_pump()?;
 	while app.running() {
+		let focus = app.focus();
 		for event in event_pump.poll_iter() {
+			// input goes to the focused M8
+			let m8 = &mut m8s[focus];
+			if !matches!(event, Event::MouseMotion { .. }) {
+				app.request_redraw();
+			}
 			match event {
 				Event::Quit { .. } => {
 					app.quit();
 					continue;
 				}
 				Event::KeyDown { keycode: Some(keycode), keymod, repeat, .. } => {
-					if keycode == Keycode::Escape {
-						app.escape_command(&mut m8, &mut canvas)?;
+					if app.remap_mode() || app.learn_mode() {
+						// any other key is taken by the remapping or ignored by the learning
+						if app.aborts_input_mode(keycode, keymod) {
+							app.abort_input_mode(m8);
+						} else if !repeat && app.remap(keycode, keymod, false) {
+							app.cancel_remap_mode();
+							app.action_modified(
+								&mut canvas,
+								m8,
+								&joystick_subsystem,
+								&audio_subsystem,
+							)?;
+						}
 						continue;
 					}
-					if repeat || app.remap_mode() {
+					if repeat {
 						continue;
 					}
-					if keymod.intersects(Mod::LALTMOD | Mod::RALTMOD) {
-						match keycode {
-							Keycode::Return => {
-								draw::toggle_fullscreen(&mut canvas)?;
-								continue;
-							}
-							Keycode::C if !app.config_mode() => {
-								app.start_config_mode();
-								m8.reset_display()?;
-								continue;
+					if let Some(ref mut replay) = replay {
+						if !app.config_mode() {
+							let mut handled = true;
+							match keycode {
+								Keycode::Space => replay.toggle_pause(),
+								Keycode::Left => replay.rewind(record::SEEK_STEP),
+								Keycode::Right => replay.forward(record::SEEK_STEP),
+								Keycode::Up => replay.faster(),
+								Keycode::Down => replay.slower(),
+								_ => handled = false,
 							}
-							Keycode::R if !app.config_mode() => {
-								m8.reset(keymod.intersects(Mod::LSHIFTMOD | Mod::RSHIFTMOD))?;
+							// the keys of the replay neither play notes nor go to the M8
+							if handled {
 								continue;
 							}
-							_ => {}
 						}
 					}
 
-					if !app.config_mode() {
-						let config = app.config();
-						if keycode == *config.rm8.keyjazz {
-							m8.keyjazz.toggle();
-						}
-						if !config.overlap || *m8.keyjazz {
-							if let Some(n) = config.keyjazz.get(&keycode.into()) {
-								m8.set_note(*n);
-							}
+					let config = app.config();
+					// a key held with modifiers for a command does not play a note
+					let modified =
+						matches!(config.key_pressed(keycode, keymod), Some((_, n)) if n > 0);
+					if !app.config_mode() && !modified && (!config.overlap || *m8.keyjazz) {
+						if let Some(n) = config.keyjazz.get(&keycode.into()) {
+							m8.set_note(*n);
 						}
 					}
-					app.handle_key(&mut m8, keycode, keymod, false);
+					app.handle_key(m8, keycode, keymod, false);
 				}
 				Event::KeyUp { keycode: Some(keycode), keymod, .. } => {
 					if app.remap_mode() {
-						if app.remap(keycode) {
+						if app.remap(keycode, keymod, true) {
 							app.cancel_remap_mode();
-							app.action_modified(&mut canvas, &mut m8, &joystick_subsystem)?;
+							app.action_modified(
+								&mut canvas,
+								m8,
+								&joystick_subsystem,
+								&audio_subsystem,
+							)?;
 						}
 						continue;
 					}
-					app.handle_key(&mut m8, keycode, keymod, true);
+					if app.learn_mode() {
+						continue;
+					}
+					app.handle_key(m8, keycode, keymod, true);
 				}
 				Event::JoyAxisMotion { which, axis_idx, value, .. } => {
-					app.handle_cmd(&mut m8, app.axis_cmd(which, axis_idx, value));
+					app.handle_cmd(m8, app.axis_cmd(which, axis_idx, value));
 				}
 				Event::JoyHatMotion { which, state, .. } => {
-					app.handle_cmd(&mut m8, app.hat_cmd(which, state));
+					app.handle_cmd(m8, app.hat_cmd(which, state));
 				}
 				Event::JoyButtonDown { which, button_idx, .. } => {
-					app.handle_cmd(&mut m8, app.button_cmd(which, button_idx, false));
+					app.handle_cmd(m8, app.button_cmd(which, button_idx, false));
 				}
 				Event::JoyButtonUp { which, button_idx, .. } => {
-					app.handle_cmd(&mut m8, app.button_cmd(which, button_idx, true));
+					app.handle_cmd(m8, app.button_cmd(which, button_idx, true));
 				}
 				Event::JoyDeviceAdded { which, .. } => {
 					app.add_joystick(&joystick_subsystem, which);
@@ -186,27 +302,78 @@ fn main() -> Result<(), String> {
 			}
 		}
 
-		app.process_key(&mut m8);
-		app.handle_defer(&mut m8, &mut canvas)?;
+		if let Some(ref midi) = midi {
+			for message in midi.messages() {
+				if !app.learn_mode() {
+					app.handle_midi(&mut m8s[focus], message);
+				} else if app.learn(message) {
+					app.action_modified(
+						&mut canvas,
+						&mut m8s[focus],
+						&joystick_subsystem,
+						&audio_subsystem,
+					)?;
+				}
+			}
+		}
+		app.process_key(&mut m8s[focus]);
+		app.handle_defer(&mut m8s, &mut canvas, &mut textures)?;
+		let focus = app.focus();
 		if app.sync() {
+			let audio_stats = m8s[focus].audio_stats();
 			if app.config_mode() {
-				app.process_action(&mut canvas, &mut m8, &joystick_subsystem, &config_file)?;
+				app.process_action(
+					&mut canvas,
+					&mut m8s[focus],
+					&joystick_subsystem,
+					&audio_subsystem,
+					&options.config_file,
+				)?;
+			}
+			// the filter of a texture is the one of its creation
+			if app.config().app.filter != filter {
+				filter = app.config().app.filter;
+				sdl2::hint::set("SDL_RENDER_SCALE_QUALITY", filter.hint());
+				for (m8, texture) in m8s.iter_mut().zip(textures.iter_mut()) {
+					let screen = m8.screen();
+					*texture = creator
+						.create_texture_target(
+							PixelFormatEnum::ARGB8888,
+							screen.width,
+							screen.height,
+						)
+						.map_err(|e| e.to_string())?;
+					m8.reset_display()?;
+				}
+			}
 
-				canvas
-					.with_texture_canvas(&mut texture, |target| {
-						let config = app.config();
-						let ctx = &mut draw::Context {
-							canvas: target,
-							font: &mut font,
-							theme: config.theme,
-							font_option: config.app.font,
-							screen_bg: None,
-						};
-						let _ = app.render(ctx);
-						let _ = app.render_fps(ctx);
-					})
-					.map_err(|e| e.to_string())?;
-			} else {
+			for (i, (m8, texture)) in m8s.iter_mut().zip(textures.iter_mut()).enumerate() {
+				// the menu replaces the screen of the focused M8, the others keep running
+				let mut damage = draw::Damage::default();
+				if app.config_mode() && i == focus {
+					canvas
+						.with_texture_canvas(texture, |target| {
+							let config = app.config();
+							let ctx = &mut draw::Context {
+								canvas: target,
+								font: &mut font,
+								theme: config.theme,
+								font_option: config.app.font,
+								screen_bg: None,
+								screen: m8.screen(),
+								damage: draw::Damage::default(),
+							};
+							let _ = app.render(ctx);
+							let _ = app.render_fps(ctx);
+							let _ = app.render_audio_stats(ctx, audio_stats);
+							damage = ctx.damage;
+						})
+						.map_err(|e| e.to_string())?;
+					damages[i].merge(damage);
+					continue;
+				}
+
+				m8.serve()?;
 				if m8.note.changed() {
 					m8.send_keyjazz()?;
 				}
@@ -215,7 +382,7 @@ fn main() -> Result<(), String> {
 				}
 
 				canvas
-					.with_texture_canvas(&mut texture, |target| {
+					.with_texture_canvas(texture, |target| {
 						let config = app.config();
 						let ctx = &mut draw::Context {
 							canvas: target,
@@ -223,12 +390,14 @@ fn main() -> Result<(), String> {
 							theme: config.theme,
 							font_option: config.app.font,
 							screen_bg: None,
+							screen: m8.screen(),
+							damage: draw::Damage::default(),
 						};
 						while let Ok(Some(cmd)) = m8.read() {
 							let _ = match cmd {
 								m8::Command::Joypad { .. } => Ok(()),
 								m8::Command::Waveform(fg, data) => ctx.draw_waveform(data, fg),
-								m8::Command::Character(c, x, y, fg, bg) => ctx.draw_char(
+								m8::Command::Character(c, x, y, fg, bg) => ctx.draw_m8_char(
 									c,
 									x as i32,
 									y as i32,
@@ -239,6 +408,8 @@ fn main() -> Result<(), String> {
 									(x as i32, y as i32, w as u32, h as u32),
 									Rgb::from_tuple(bg),
 								),
+								// the screen of the M8 already follows its model and its font
+								m8::Command::SystemInfo(_) => Ok(()),
 							};
 						}
 						if m8.disconnected() {
@@ -246,7 +417,7 @@ fn main() -> Result<(), String> {
 							let fg = ctx.theme.text_info;
 							let _ = ctx.draw_str_centered(
 								"M8 LOST",
-								m8::SCREEN_HEIGHT as i32 / 2,
+								ctx.screen.height as i32 / 2,
 								fg,
 								fg,
 							);
@@ -262,17 +433,123 @@ fn main() -> Result<(), String> {
 						if let Some(bg) = ctx.screen_bg {
 							app.config_mut().theme.screen = bg;
 						}
-						let _ = app.render_fps(ctx);
+						if i == focus {
+							let _ = app.render_fps(ctx);
+							let _ = app.render_audio_stats(ctx, audio_stats);
+						}
+						damage = ctx.damage;
 					})
 					.map_err(|e| e.to_string())?;
+				damages[i].merge(damage);
+				let (screen, query) = (m8.screen(), texture.query());
+				if (screen.width, screen.height) != (query.width, query.height) {
+					*texture = creator
+						.create_texture_target(
+							PixelFormatEnum::ARGB8888,
+							screen.width,
+							screen.height,
+						)
+						.map_err(|e| e.to_string())?;
+					m8.reset_display()?;
+				}
 			}
 
+			// the screens are as large as the largest M8 one
+			if let Some(screen) = m8s.iter().map(M8::screen).max_by_key(|s| s.width * s.height) {
+				app.set_screen(&mut canvas, screen)?;
+			}
+			app.record_frame(&mut canvas, &mut textures[focus])?;
+			if let Some(ref replay) = replay {
+				canvas.window_mut().set_title(&replay.status()).map_err(|e| e.to_string())?;
+			}
+			// the window is drawn again only when something changed, whole since the previous
+			// frame is lost once presented
+			let tiles = app.tiles();
+			let damaged = tiles.iter().any(|&(i, _)| !damages[i].is_empty());
+			if !app.needs_redraw(damaged) {
+				continue;
+			}
+			app.set_damage(damages[focus].take());
+			damages.iter_mut().for_each(|damage| {
+				damage.take();
+			});
+			app.apply_scaling(&mut canvas)?;
 			canvas.set_draw_color(app.config().theme.screen.rgb());
 			canvas.clear();
-			canvas.copy(&texture, None, None)?;
+			let size = (app.screen().width, app.screen().height);
+			for &(i, rect) in tiles.iter() {
+				canvas.copy(&textures[i], None, rect)?;
+				overlay.draw(&mut canvas, app.config().app.effect, size, rect)?;
+			}
+			let focused = tiles.iter().find(|&&(i, _)| i == focus).map(|&(_, rect)| rect);
+			if let (true, Some(rect)) = (tiles.len() > 1, focused) {
+				canvas.set_draw_color(app.config().theme.cursor.rgb());
+				canvas.draw_rect(rect)?;
+			}
+			// the meters are drawn over the screen rather than on it, so that the M8 display
+			// is left untouched
+			if let (true, false, Some(rect)) = (app.meters(), app.config_mode(), focused) {
+				let ctx = &mut draw::Context {
+					canvas: &mut canvas,
+					font: &mut font,
+					theme: app.config().theme,
+					font_option: app.config().app.font,
+					screen_bg: None,
+					screen: app.screen(),
+					damage: draw::Damage::default(),
+				};
+				app.render_meters(ctx, &mut m8s[focus], (rect.x(), rect.y()))?;
+			}
+			if let (true, false, Some(rect)) = (app.diagnostics(), app.config_mode(), focused) {
+				let ctx = &mut draw::Context {
+					canvas: &mut canvas,
+					font: &mut font,
+					theme: app.config().theme,
+					font_option: app.config().app.font,
+					screen_bg: None,
+					screen: app.screen(),
+					damage: draw::Damage::default(),
+				};
+				app.render_diagnostics(ctx, &m8s[focus], (rect.x(), rect.y()))?;
+			}
+			if let (true, false, Some(rect)) = (app.status_shown(), app.config_mode(), focused) {
+				let ctx = &mut draw::Context {
+					canvas: &mut canvas,
+					font: &mut font,
+					theme: app.config().theme,
+					font_option: app.config().app.font,
+					screen_bg: None,
+					screen: app.screen(),
+					damage: draw::Damage::default(),
+				};
+				app.render_status(ctx, (rect.x(), rect.y()))?;
+			}
 			canvas.present();
 		}
 	}
 
+	for m8 in m8s.iter() {
+		let stats = m8.link_stats();
+		let errors = stats.malformed + stats.overflows + stats.invalid + stats.unknown;
+		if errors > 0 {
+			eprintln!(
+				"{}: {} frames, {} dropped ({} malformed, {} overflows, {} invalid, {} unknown)",
+				m8.device_name().unwrap_or_default(),
+				stats.frames,
+				errors,
+				stats.malformed,
+				stats.overflows,
+				stats.invalid,
+				stats.unknown
+			);
+		}
+	}
+	if app.config().app.show_fps {
+		let cpu = app.cpu();
+		if let (Some(active), Some(idle)) = (cpu.active(), cpu.idle()) {
+			eprintln!("cpu usage: {:.1}% active, {:.1}% idle", active, idle);
+		}
+	}
+
 	Ok(())
 }
@@ -1,10 +1,13 @@
+use sdl2::AudioSubsystem;
+use std::ops::Range;
+
 use crate::{
-	config::{Command, Config, DEFAULT_SENSIBILITY},
+	config::{Command, Config, Effect, Filter, Scaling, DEFAULT_SENSIBILITY},
 	m8::M8,
 	nav::{Navigation, Page},
 };
 
-pub fn build_menu(menu: &mut Navigation, m8: &M8, config: &Config) {
+pub fn build_menu(menu: &mut Navigation, m8: &M8, audio: &AudioSubsystem, config: &Config) {
 	let mut theme_page = Page::new("THEME", 'T');
 	theme_page.add_rgb("TEXT:DEFAULT", config.theme.text_default);
 	theme_page.add_rgb("TEXT:VALUE", config.theme.text_value);
@@ -22,45 +25,81 @@ pub fn build_menu(menu: &mut Navigation, m8: &M8, config: &Config) {
 	let mut app_page = Page::new("CONFIG", 'C');
 	app_page.add_bool("FULLSCREEN", config.app.fullscreen);
 	app_page.add_int("ZOOM", config.app.zoom as usize, 1, 9, 2);
+	app_page.add_choice("SCALING", Scaling::NAMES, config.app.scaling as usize);
+	app_page.add_choice("FILTER", Filter::NAMES, config.app.filter as usize);
+	app_page.add_choice("EFFECT", Effect::NAMES, config.app.effect as usize);
 	app_page.add_font("FONT", config.app.font);
 	app_page.add_int("KEY SENS.", config.app.key_sensibility as usize, 60, 200, 10);
 	app_page.add_bool("SHOW_FPS", config.app.show_fps);
 	app_page.add_int("FPS", config.app.fps, 1, 200, 10);
 	app_page.add_bool("RECONNECT", config.app.reconnect);
 	app_page.add_device("DEVICE", m8.device_name());
-	app_page.add_audio("AUDIO", m8.capture_device_name());
 	app_page.add_empty();
 	app_page.add_action2("RESET", "SAVE");
 	app_page.add_page_above(theme_page);
 
-	let mut rm8key_page = Page::new("RM8 KEYS", 'R');
-	rm8key_page.add_key("KEYJAZZ", *config.rm8.keyjazz);
-	rm8key_page.add_key("VELOCITY-", *config.rm8.velocity_minus);
-	rm8key_page.add_key("VELOCITY+", *config.rm8.velocity_plus);
-	rm8key_page.add_key("OCTAVE-", *config.rm8.octave_minus);
-	rm8key_page.add_key("OCTAVE+", *config.rm8.octave_plus);
-	rm8key_page.add_empty();
-	rm8key_page.add_action3("REMAP", "RESET", "SAVE");
+	let mut audio_page = Page::new("AUDIO", 'S');
+	audio_page.add_bool("ENABLED", config.audio.enabled);
+	let capture = m8.capture_device_name().or_else(|| config.audio.capture.clone());
+	audio_page.add_audio("CAPTURE", audio, true, capture);
+	let playback = m8.playback_device_name().or_else(|| config.audio.playback.clone());
+	audio_page.add_audio("PLAYBACK", audio, false, playback);
+	audio_page.add_int("SAMPLES", config.audio.samples.unwrap_or(0) as usize, 0, 8192, 256);
+	audio_page.add_int("RATE", config.audio.rate as usize, 8000, 192000, 100);
+	audio_page.add_int("LATENCY", config.audio.latency as usize, 1, 1000, 10);
+	audio_page.add_bool("SHOW STATS", config.audio.show_stats);
+	audio_page.add_empty();
+	audio_page.add_action2("RESET", "SAVE");
+	app_page.add_page_below(audio_page);
+
+	let fx = &config.audio_fx;
+	let mut fx_page = Page::new("AUDIO FX", 'F');
+	fx_page.add_signed_int("GAIN", fx.gain as isize, -24, 24, 6);
+	fx_page.add_bool("MUTE", fx.mute);
+	fx_page.add_bool("LIMITER", fx.limiter);
+	fx_page.add_signed_int("THRESHOLD", fx.threshold as isize, -24, 0, 6);
+	fx_page.add_signed_int("EQ LOW", fx.low as isize, -12, 12, 3);
+	fx_page.add_signed_int("EQ MID", fx.mid as isize, -12, 12, 3);
+	fx_page.add_signed_int("EQ HIGH", fx.high as isize, -12, 12, 3);
+	fx_page.add_empty();
+	fx_page.add_action2("RESET", "SAVE");
+	app_page.add_page_below(fx_page);
 
-	let mut m8key_page = Page::new("M8 KEYS", 'K');
-	m8key_page.add_key("UP", *config.m8.up);
-	m8key_page.add_key("DOWN", *config.m8.down);
-	m8key_page.add_key("LEFT", *config.m8.left);
-	m8key_page.add_key("RIGHT", *config.m8.right);
-	m8key_page.add_key("EDIT", *config.m8.edit);
-	m8key_page.add_key("OPTION", *config.m8.option);
-	m8key_page.add_key("SHIFT", *config.m8.shift);
-	m8key_page.add_key("PLAY", *config.m8.play);
-	m8key_page.add_empty();
-	m8key_page.add_action3("REMAP", "RESET", "SAVE");
+	let key_page = |title: &str, short_name, commands: Range<usize>| {
+		let mut page = Page::new(title, short_name);
+		for (cmd, name) in &Command::ALL[commands] {
+			page.add_key(*name, config.binding(*cmd).cloned().unwrap_or_default());
+		}
+		page.add_empty();
+		page.add_action3("REMAP", "RESET", "SAVE");
+		page
+	};
+	let rm8key_page = key_page("RM8 KEYS", 'R', Command::RM8_KEYS);
+	let appkey_page = key_page("APP KEYS", 'P', Command::APP_KEYS);
+	let mut m8key_page = key_page("M8 KEYS", 'K', Command::M8_KEYS);
 	m8key_page.add_page_below(rm8key_page);
+	m8key_page.add_page_below(appkey_page);
 
 	let mut empty_joystick_page = Page::new("JOYSTICK", 'J');
 	empty_joystick_page.add_info("N.JOYSTICKS", "0");
 
+	let midi_page = |title: &str, short_name, commands: Range<usize>| {
+		let mut page = Page::new(title, short_name);
+		for (cmd, name) in &Command::ALL[commands] {
+			page.add_midi(*name, config.midi.control(*cmd));
+		}
+		page.add_empty();
+		page.add_action3("LEARN", "RESET", "SAVE");
+		page
+	};
+	let mut m8midi_page = midi_page("MIDI", 'M', Command::M8_KEYS);
+	m8midi_page.add_page_below(midi_page("RM8 MIDI", 'N', Command::RM8_KEYS));
+	m8midi_page.add_page_below(midi_page("APP MIDI", 'I', Command::APP_KEYS));
+
 	menu.add_page(app_page);
 	menu.add_page(m8key_page);
 	menu.add_page(empty_joystick_page);
+	menu.add_page(m8midi_page);
 }
 
 pub fn build_joystick_page() -> Option<Page> {
@@ -1,5 +1,6 @@
 use crate::{
 	config::{self, Axis, Command, Config, HatConfig, JoystickConfig},
+	midi::Control,
 	nav::{Input, Item, Navigation, Page},
 };
 use sdl2::{joystick::Joystick, JoystickSubsystem};
@@ -233,49 +234,62 @@ pub fn joystick_has_hats(page: &Page) -> bool {
 	false
 }
 
-pub fn m8_to_page(page: &mut Page, config: &config::Config) {
-	let mut items = page.items_mut();
-	key_to_item(items.next(), config.m8.up);
-	key_to_item(items.next(), config.m8.down);
-	key_to_item(items.next(), config.m8.left);
-	key_to_item(items.next(), config.m8.right);
-	key_to_item(items.next(), config.m8.edit);
-	key_to_item(items.next(), config.m8.option);
-	key_to_item(items.next(), config.m8.shift);
-	key_to_item(items.next(), config.m8.play);
+/// Commands of the keys of the `M8 KEYS`, `RM8 KEYS` and `APP KEYS` pages, or of the controls of
+/// the MIDI pages, in their order; none for the other pages.
+pub fn page_commands(page: &Page) -> &'static [(Command, &'static str)] {
+	let commands = match page.short_name() {
+		'K' | 'M' => Command::M8_KEYS,
+		'R' | 'N' => Command::RM8_KEYS,
+		'P' | 'I' => Command::APP_KEYS,
+		_ => return &[],
+	};
+	&Command::ALL[commands]
 }
 
-pub fn rm8_to_page(page: &mut Page, config: &config::Config) {
-	let mut items = page.items_mut();
-	key_to_item(items.next(), config.rm8.keyjazz);
-	key_to_item(items.next(), config.rm8.velocity_minus);
-	key_to_item(items.next(), config.rm8.velocity_plus);
-	key_to_item(items.next(), config.rm8.octave_minus);
-	key_to_item(items.next(), config.rm8.octave_plus);
+pub fn keys_to_page(page: &mut Page, config: &config::Config) {
+	let commands = page_commands(page);
+	for (item, (cmd, _)) in page.items_mut().zip(commands) {
+		if let Some(keys) = config.binding(*cmd) {
+			key_to_item(Some(item), keys);
+		}
+	}
 }
 
-pub fn rm8_keys_from_page(page: &Page) -> config::RM8KeyboardConfig {
-	let mut items = page.items();
-	config::RM8KeyboardConfig {
-		keyjazz: key_from_item(items.next()),
-		velocity_minus: key_from_item(items.next()),
-		velocity_plus: key_from_item(items.next()),
-		octave_minus: key_from_item(items.next()),
-		octave_plus: key_from_item(items.next()),
+pub fn keys_from_page(page: &Page, config: &mut config::Config) {
+	for (item, (cmd, _)) in page.items().zip(page_commands(page)) {
+		if let Some(keys) = config.binding_mut(*cmd) {
+			*keys = key_from_item(Some(item));
+		}
 	}
 }
 
-pub fn m8_keys_from_page(page: &Page) -> config::M8KeyboardConfig {
-	let mut items = page.items();
-	config::M8KeyboardConfig {
-		up: key_from_item(items.next()),
-		down: key_from_item(items.next()),
-		left: key_from_item(items.next()),
-		right: key_from_item(items.next()),
-		edit: key_from_item(items.next()),
-		option: key_from_item(items.next()),
-		shift: key_from_item(items.next()),
-		play: key_from_item(items.next()),
+pub fn midi_to_page(page: &mut Page, config: &config::Config) {
+	let commands = page_commands(page);
+	for (item, (cmd, _)) in page.items_mut().zip(commands) {
+		if let Item::Input(_, Input::Midi(m)) = item {
+			m.set_value(config.midi.control(*cmd));
+		}
+	}
+}
+
+/// Updates the notes and the controllers of a MIDI page, keeping the other commands.
+pub fn midi_from_page(page: &Page, config: &mut config::MidiConfig) {
+	let commands = page_commands(page);
+	let on_page = |cmd: &Command| commands.iter().any(|(c, _)| c == cmd);
+	config.notes.retain(|_, cmd| !on_page(cmd));
+	config.controls.retain(|_, cmd| !on_page(cmd));
+	for (item, (cmd, _)) in page.items().zip(commands) {
+		if let Item::Input(_, Input::Midi(m)) = item {
+			match m.value() {
+				Some(Control::Note(note)) => {
+					config.notes.insert(note, *cmd);
+				}
+				Some(Control::Controller(controller)) => {
+					config.controls.insert(controller, *cmd);
+				}
+				None => {}
+			}
+		}
 	}
 }
 
@@ -313,6 +327,9 @@ pub fn app_to_page(page: &mut Page, config: &Config) {
 	let mut items = page.items_mut();
 	bool_to_item(items.next(), config.app.fullscreen);
 	int_to_item(items.next(), config.app.zoom as usize);
+	choice_to_item(items.next(), config.app.scaling as usize);
+	choice_to_item(items.next(), config.app.filter as usize);
+	choice_to_item(items.next(), config.app.effect as usize);
 	font_to_item(items.next(), config.app.font);
 	int_to_item(items.next(), config.app.key_sensibility as usize);
 	bool_to_item(items.next(), config.app.show_fps);
@@ -325,6 +342,9 @@ pub fn app_from_page(page: &Page) -> config::AppConfig {
 	config::AppConfig {
 		fullscreen: bool_from_item(items.next()),
 		zoom: int_from_item(items.next()) as u32,
+		scaling: config::Scaling::ALL[choice_from_item(items.next())],
+		filter: config::Filter::ALL[choice_from_item(items.next())],
+		effect: config::Effect::ALL[choice_from_item(items.next())],
 		font: font_from_item(items.next()),
 		key_sensibility: int_from_item(items.next()) as u64,
 		show_fps: bool_from_item(items.next()),
@@ -333,6 +353,86 @@ pub fn app_from_page(page: &Page) -> config::AppConfig {
 	}
 }
 
+pub fn audio_to_page(page: &mut Page, config: &Config) {
+	let mut items = page.items_mut();
+	bool_to_item(items.next(), config.audio.enabled);
+	audio_to_item(items.next(), config.audio.capture.as_deref());
+	audio_to_item(items.next(), config.audio.playback.as_deref());
+	int_to_item(items.next(), config.audio.samples.unwrap_or(0) as usize);
+	int_to_item(items.next(), config.audio.rate as usize);
+	int_to_item(items.next(), config.audio.latency as usize);
+	bool_to_item(items.next(), config.audio.show_stats);
+}
+
+pub fn audio_from_page(page: &Page) -> config::AudioConfig {
+	let mut items = page.items();
+	config::AudioConfig {
+		enabled: bool_from_item(items.next()),
+		capture: audio_from_item(items.next()),
+		playback: audio_from_item(items.next()),
+		samples: match int_from_item(items.next()) {
+			0 => None,
+			samples => Some(samples as u16),
+		},
+		rate: int_from_item(items.next()) as i32,
+		latency: int_from_item(items.next()) as u32,
+		show_stats: bool_from_item(items.next()),
+	}
+}
+
+pub fn audio_fx_to_page(page: &mut Page, config: &Config) {
+	let fx = &config.audio_fx;
+	let mut items = page.items_mut();
+	signed_to_item(items.next(), fx.gain);
+	bool_to_item(items.next(), fx.mute);
+	bool_to_item(items.next(), fx.limiter);
+	signed_to_item(items.next(), fx.threshold);
+	signed_to_item(items.next(), fx.low);
+	signed_to_item(items.next(), fx.mid);
+	signed_to_item(items.next(), fx.high);
+}
+
+pub fn audio_fx_from_page(page: &Page) -> config::AudioFxConfig {
+	let mut items = page.items();
+	config::AudioFxConfig {
+		gain: signed_from_item(items.next()),
+		mute: bool_from_item(items.next()),
+		limiter: bool_from_item(items.next()),
+		threshold: signed_from_item(items.next()),
+		low: signed_from_item(items.next()),
+		mid: signed_from_item(items.next()),
+		high: signed_from_item(items.next()),
+	}
+}
+
+fn signed_to_item(item: Option<&mut Item>, i: i32) {
+	if let Some(Item::Input(_, Input::Int(value))) = item {
+		value.set_signed_value(i as isize);
+	}
+}
+
+fn signed_from_item(item: Option<&Item>) -> i32 {
+	if let Some(Item::Input(_, Input::Int(i))) = item {
+		i.signed_value() as i32
+	} else {
+		0
+	}
+}
+
+fn audio_to_item(item: Option<&mut Item>, device: Option<&str>) {
+	if let Some(Item::Input(_, Input::Audio(audio))) = item {
+		audio.select(device);
+	}
+}
+
+fn audio_from_item(item: Option<&Item>) -> Option<String> {
+	if let Some(Item::Input(_, Input::Audio(audio))) = item {
+		audio.value().map(|dev| dev.to_string())
+	} else {
+		None
+	}
+}
+
 fn cmd_to_item(item: Option<&mut Item>, cmd: Command) {
 	if let Some(Item::Input(_, Input::Command(c))) = item {
 		*c = cmd;
@@ -375,6 +475,20 @@ fn font_from_item(item: Option<&Item>) -> config::Font {
 	}
 }
 
+fn choice_to_item(item: Option<&mut Item>, value: usize) {
+	if let Some(Item::Input(_, Input::Choice(c))) = item {
+		c.set_value(value)
+	}
+}
+
+fn choice_from_item(item: Option<&Item>) -> usize {
+	if let Some(Item::Input(_, Input::Choice(c))) = item {
+		c.value()
+	} else {
+		0
+	}
+}
+
 fn bool_to_item(item: Option<&mut Item>, b: bool) {
 	if let Some(Item::Input(_, Input::Bool(value))) = item {
 		value.set_value(b);
@@ -403,15 +517,15 @@ fn rgb_from_item(item: Option<&Item>) -> config::Rgb {
 	}
 }
 
-fn key_to_item(item: Option<&mut Item>, value: config::Keycode) {
+fn key_to_item(item: Option<&mut Item>, value: &config::Bindings) {
 	if let Some(Item::Input(_, Input::Key(key))) = item {
-		key.set_value(*value);
+		key.set_value(value.clone());
 	}
 }
 
-fn key_from_item(item: Option<&Item>) -> config::Keycode {
+fn key_from_item(item: Option<&Item>) -> config::Bindings {
 	if let Some(Item::Input(_, Input::Key(k))) = item {
-		k.value().into()
+		k.value().clone()
 	} else {
 		sdl2::keyboard::Keycode::Power.into()
 	}
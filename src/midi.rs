@@ -0,0 +1,325 @@
+use midir::{os::unix::VirtualInput, Ignore, MidiInputConnection};
+use std::{
+	fmt,
+	fs::{self, OpenOptions},
+	io::{self, Read},
+	os::unix::fs::OpenOptionsExt,
+	sync::{
+		atomic::{AtomicBool, Ordering},
+		mpsc, Arc,
+	},
+	thread::{self, JoinHandle},
+	time::Duration,
+};
+
+/// Where ALSA puts its raw MIDI devices, the virtual ones of `snd-virmidi` included.
+const DEVICES: &str = "/dev/snd";
+/// Name of rm8 in the sequencer, and of its virtual port.
+const CLIENT: &str = "rm8";
+/// The device making a virtual sequencer port.
+pub const VIRTUAL: &str = "virtual";
+/// How long a raw device is left alone when it has nothing to read.
+const POLL_DELAY: Duration = Duration::from_millis(10);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Message {
+	NoteOn { channel: u8, note: u8, velocity: u8 },
+	NoteOff { channel: u8, note: u8 },
+	Control { channel: u8, controller: u8, value: u8 },
+}
+
+impl Message {
+	/// Returns the note or the controller pressed, to be learnt.
+	pub fn pressed(&self) -> Option<Control> {
+		match *self {
+			Message::NoteOn { note, .. } => Some(Control::Note(note)),
+			Message::Control { controller, value, .. } if value > 0 => {
+				Some(Control::Controller(controller))
+			}
+			_ => None,
+		}
+	}
+}
+
+/// A note or a controller bound to a command.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Control {
+	Note(u8),
+	Controller(u8),
+}
+
+impl fmt::Display for Control {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			Control::Note(note) => write!(f, "NOTE {}", note),
+			Control::Controller(controller) => write!(f, "CC {}", controller),
+		}
+	}
+}
+
+/// Turns the bytes of a MIDI stream into messages, running status included.
+#[derive(Default)]
+pub struct Parser {
+	status: u8,
+	data: [u8; 2],
+	len: usize,
+}
+
+impl Parser {
+	pub fn parse(&mut self, byte: u8) -> Option<Message> {
+		match byte {
+			// real time messages may come anywhere, even between data bytes
+			0xf8..=0xff => None,
+			// system messages cancel the running status, their data is skipped
+			0xf0..=0xf7 => {
+				self.status = 0;
+				None
+			}
+			0x80..=0xef => {
+				self.status = byte;
+				self.len = 0;
+				None
+			}
+			_ if self.status == 0 => None,
+			_ => {
+				self.data[self.len] = byte;
+				self.len += 1;
+				let len = match self.status & 0xf0 {
+					0xc0 | 0xd0 => 1,
+					_ => 2,
+				};
+				if self.len < len {
+					return None;
+				}
+				self.len = 0;
+				self.message()
+			}
+		}
+	}
+
+	fn message(&self) -> Option<Message> {
+		let channel = self.status & 0x0f;
+		let [note, velocity] = self.data;
+		match self.status & 0xf0 {
+			0x90 if velocity > 0 => Some(Message::NoteOn { channel, note, velocity }),
+			0x80 | 0x90 => Some(Message::NoteOff { channel, note }),
+			0xb0 => Some(Message::Control { channel, controller: note, value: velocity }),
+			_ => None,
+		}
+	}
+}
+
+/// Reads the messages of a MIDI port on a thread of its own, until it is dropped.
+pub struct MidiInput {
+	receiver: mpsc::Receiver<Message>,
+	/// The sequencer connection, closed when dropped.
+	_connection: Option<MidiInputConnection<()>>,
+	stop: Arc<AtomicBool>,
+	reader: Option<JoinHandle<()>>,
+}
+
+impl MidiInput {
+	/// Opens `device`: `virtual` for a sequencer port of rm8 that other programs connect to, a
+	/// raw device by its path, or else the first sequencer port whose name contains `device`.
+	pub fn open(device: &str) -> Result<Self, String> {
+		let (sender, receiver) = mpsc::channel();
+		let stop = Arc::new(AtomicBool::new(false));
+		let (connection, reader) = if device.starts_with('/') {
+			(None, Some(read_raw(device, sender, stop.clone())?))
+		} else {
+			(Some(connect(device, sender)?), None)
+		};
+		Ok(Self { receiver, _connection: connection, stop, reader })
+	}
+
+	/// Returns the messages received since the last call.
+	pub fn messages(&self) -> impl Iterator<Item = Message> + '_ {
+		self.receiver.try_iter()
+	}
+}
+
+impl Drop for MidiInput {
+	fn drop(&mut self) {
+		self.stop.store(true, Ordering::Relaxed);
+		if let Some(reader) = self.reader.take() {
+			let _ = reader.join();
+		}
+	}
+}
+
+/// Connects to a sequencer port, or makes a virtual one.
+fn connect(device: &str, sender: mpsc::Sender<Message>) -> Result<MidiInputConnection<()>, String> {
+	let mut input = midir::MidiInput::new(CLIENT).map_err(|e| e.to_string())?;
+	input.ignore(Ignore::All);
+	let mut parser = Parser::default();
+	let callback = move |_: u64, bytes: &[u8], _: &mut ()| {
+		for &byte in bytes {
+			if let Some(message) = parser.parse(byte) {
+				let _ = sender.send(message);
+			}
+		}
+	};
+	if device == VIRTUAL {
+		return input.create_virtual(CLIENT, callback, ()).map_err(|e| e.to_string());
+	}
+	let port = input
+		.ports()
+		.into_iter()
+		.find(|port| input.port_name(port).is_ok_and(|name| name.contains(device)))
+		.ok_or_else(|| format!("{}: no such MIDI port", device))?;
+	input.connect(&port, CLIENT, callback, ()).map_err(|e| e.to_string())
+}
+
+/// Reads a raw device until `stop` is set or the device is unplugged.
+fn read_raw(
+	device: &str,
+	sender: mpsc::Sender<Message>,
+	stop: Arc<AtomicBool>,
+) -> Result<JoinHandle<()>, String> {
+	// not blocking, to notice when rm8 stops listening
+	let mut file = OpenOptions::new()
+		.read(true)
+		.custom_flags(libc::O_NONBLOCK)
+		.open(device)
+		.map_err(|e| format!("{}: {}", device, e))?;
+	Ok(thread::spawn(move || {
+		let mut parser = Parser::default();
+		let mut buf = [0; 64];
+		while !stop.load(Ordering::Relaxed) {
+			match file.read(&mut buf) {
+				Ok(n) if n > 0 => {
+					for &byte in buf[..n].iter() {
+						if let Some(message) = parser.parse(byte) {
+							if sender.send(message).is_err() {
+								return;
+							}
+						}
+					}
+				}
+				Ok(_) => thread::sleep(POLL_DELAY),
+				Err(e) if e.kind() == io::ErrorKind::WouldBlock => thread::sleep(POLL_DELAY),
+				Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+				Err(_) => break,
+			}
+		}
+	}))
+}
+
+/// Returns the sequencer ports and the raw MIDI devices found.
+pub fn list_devices() -> Vec<String> {
+	let mut ports: Vec<String> = match midir::MidiInput::new(CLIENT) {
+		Ok(input) => input.ports().iter().filter_map(|port| input.port_name(port).ok()).collect(),
+		Err(_) => Vec::new(),
+	};
+	let mut devices: Vec<String> = match fs::read_dir(DEVICES) {
+		Ok(dir) => dir
+			.filter_map(|entry| entry.ok())
+			.map(|entry| entry.file_name().to_string_lossy().into_owned())
+			.filter(|name| name.starts_with("midiC"))
+			.map(|name| format!("{}/{}", DEVICES, name))
+			.collect(),
+		Err(_) => Vec::new(),
+	};
+	devices.sort();
+	ports.append(&mut devices);
+	ports
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn parse(bytes: &[u8]) -> Vec<Message> {
+		let mut parser = Parser::default();
+		bytes.iter().filter_map(|&byte| parser.parse(byte)).collect()
+	}
+
+	#[test]
+	fn keeps_the_running_status() {
+		let messages = parse(&[0x91, 60, 100, 62, 90, 0x81, 60, 0, 62, 0]);
+		assert_eq!(
+			messages,
+			[
+				Message::NoteOn { channel: 1, note: 60, velocity: 100 },
+				Message::NoteOn { channel: 1, note: 62, velocity: 90 },
+				Message::NoteOff { channel: 1, note: 60 },
+				Message::NoteOff { channel: 1, note: 62 },
+			]
+		);
+	}
+
+	#[test]
+	fn takes_a_note_on_without_velocity_as_a_note_off() {
+		let messages = parse(&[0x90, 60, 100, 60, 0]);
+		assert_eq!(
+			messages,
+			[
+				Message::NoteOn { channel: 0, note: 60, velocity: 100 },
+				Message::NoteOff { channel: 0, note: 60 },
+			]
+		);
+	}
+
+	#[test]
+	fn skips_real_time_bytes_between_data_bytes() {
+		// clock and active sensing, then a system exclusive which cancels the running status
+		let messages = parse(&[0x90, 0xf8, 60, 0xfe, 100, 0xf0, 1, 2, 0xf7, 62, 100]);
+		assert_eq!(messages, [Message::NoteOn { channel: 0, note: 60, velocity: 100 }]);
+	}
+
+	#[test]
+	fn reads_the_controllers() {
+		let messages = parse(&[0xb2, 64, 127, 64, 0]);
+		assert_eq!(
+			messages,
+			[
+				Message::Control { channel: 2, controller: 64, value: 127 },
+				Message::Control { channel: 2, controller: 64, value: 0 },
+			]
+		);
+		assert_eq!(messages[0].pressed(), Some(Control::Controller(64)));
+		assert_eq!(messages[1].pressed(), None);
+	}
+
+	#[test]
+	fn reads_a_note_on_and_off_from_one_buffer() {
+		let mut parser = Parser::default();
+		let buf = [0x90, 60, 100, 0x80, 60, 64];
+		let messages: Vec<_> = buf.iter().filter_map(|&byte| parser.parse(byte)).collect();
+		assert_eq!(
+			messages,
+			[
+				Message::NoteOn { channel: 0, note: 60, velocity: 100 },
+				Message::NoteOff { channel: 0, note: 60 },
+			]
+		);
+	}
+
+	#[test]
+	fn reads_a_raw_device_until_dropped() {
+		let path = std::env::temp_dir().join(format!("rm8-midi-{}", std::process::id()));
+		let name = std::ffi::CString::new(path.to_str().unwrap()).unwrap();
+		assert_eq!(unsafe { libc::mkfifo(name.as_ptr(), 0o600) }, 0);
+		let midi = MidiInput::open(path.to_str().unwrap()).unwrap();
+		let mut device = fs::OpenOptions::new().write(true).open(&path).unwrap();
+		std::io::Write::write_all(&mut device, &[0x90, 60, 100, 60, 0]).unwrap();
+		let mut messages = Vec::new();
+		for _ in 0..100 {
+			messages.extend(midi.messages());
+			if messages.len() == 2 {
+				break;
+			}
+			thread::sleep(POLL_DELAY);
+		}
+		assert_eq!(
+			messages,
+			[
+				Message::NoteOn { channel: 0, note: 60, velocity: 100 },
+				Message::NoteOff { channel: 0, note: 60 },
+			]
+		);
+		// the reader stops although the device is still open
+		drop(midi);
+		fs::remove_file(&path).unwrap();
+	}
+}
@@ -1,6 +1,6 @@
 use crate::{
 	draw::{Context, LINE_HEIGHT},
-	font, m8,
+	font,
 };
 
 pub use crate::{
@@ -8,8 +8,9 @@ pub use crate::{
 	nav_page::Page,
 };
 
-const MENU_X: i32 = 288;
-const MENU_Y: i32 = 200;
+/// Position of the menu from the bottom right corner of the screen.
+const MENU_X: i32 = 32;
+const MENU_Y: i32 = 40;
 
 #[derive(Debug)]
 pub struct Navigation {
@@ -129,7 +130,7 @@ impl Navigation {
 	) -> Result<(), String> {
 		let fg = ctx.theme.text_info;
 		let hl = ctx.theme.text_title;
-		let mut y = MENU_Y + line_height;
+		let mut y = ctx.screen.height as i32 - MENU_Y + line_height;
 		for (i, s) in sub.iter().enumerate() {
 			match selected {
 				Some(sel) if sel == i => ctx.draw_char(s.short_name() as u8, x, y, hl, hl)?,
@@ -143,7 +144,8 @@ impl Navigation {
 	fn draw_menu(&self, ctx: &mut Context<'_, '_, '_>) -> Result<(), String> {
 		let fg = ctx.theme.text_info;
 		let hl = ctx.theme.text_title;
-		let mut x = MENU_X;
+		let mut x = ctx.screen.width as i32 - MENU_X;
+		let y = ctx.screen.height as i32 - MENU_Y;
 		for (i, page) in self.pages.iter().enumerate() {
 			let page_y = self.page.1.saturating_abs() as usize;
 			let sel_above =
@@ -153,9 +155,9 @@ impl Navigation {
 			self.draw_sub_menu(ctx, self.pages[i].above(), x, -(LINE_HEIGHT as i32), sel_above)?;
 			self.draw_sub_menu(ctx, self.pages[i].below(), x, LINE_HEIGHT as i32, sel_below)?;
 			if self.page.0 == i && self.page.1 == 0 {
-				ctx.draw_char(page.short_name() as u8, x, MENU_Y, hl, hl)?;
+				ctx.draw_char(page.short_name() as u8, x, y, hl, hl)?;
 			} else {
-				ctx.draw_char(page.short_name() as u8, x, MENU_Y, fg, fg)?;
+				ctx.draw_char(page.short_name() as u8, x, y, fg, fg)?;
 			}
 			x += font::width(0);
 		}
@@ -164,7 +166,7 @@ impl Navigation {
 
 	pub fn draw(&mut self, ctx: &mut Context<'_, '_, '_>) -> Result<(), String> {
 		if self.changed {
-			ctx.draw_rect((0, 0, m8::SCREEN_WIDTH, m8::SCREEN_HEIGHT), ctx.theme.screen)?;
+			ctx.clear()?;
 			self.changed = false;
 		}
 		self.page_mut().draw(ctx)?;
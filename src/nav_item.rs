@@ -1,11 +1,13 @@
-use sdl2::keyboard::Keycode;
+use sdl2::AudioSubsystem;
 use std::fmt::Write;
 
 use crate::{
+	audio,
 	config::{self, Command},
 	draw::{Context, LINE_HEIGHT},
 	font,
 	m8::M8,
+	midi,
 };
 
 fn inc_hex(hex: &mut u8, add: u8) -> bool {
@@ -72,17 +74,43 @@ pub struct Int {
 	min: usize,
 	max: usize,
 	step: usize,
+	offset: isize,
 }
 
 impl Int {
 	pub fn new(value: usize, min: usize, max: usize, step: usize) -> Self {
-		Self { init: value, value, max, min, step }
+		Self { init: value, value, max, min, step, offset: 0 }
+	}
+
+	/// An integer that can be negative, it is displayed with its sign.
+	pub fn signed(value: isize, min: isize, max: isize, step: usize) -> Self {
+		let offset = -min;
+		let value = (value.clamp(min, max) + offset) as usize;
+		Self { init: value, value, max: (max + offset) as usize, min: 0, step, offset }
 	}
 
 	pub fn value(&self) -> usize {
 		self.value
 	}
 
+	pub fn signed_value(&self) -> isize {
+		self.value as isize - self.offset
+	}
+
+	pub fn set_signed_value(&mut self, value: isize) {
+		if value + self.offset >= 0 {
+			self.set_value((value + self.offset) as usize);
+		}
+	}
+
+	fn text(&self) -> String {
+		if self.offset == 0 {
+			format!("{}", self.value)
+		} else {
+			format!("{:+}", self.signed_value())
+		}
+	}
+
 	pub fn set_max(&mut self, max: usize) {
 		if max >= self.min {
 			self.max = max;
@@ -101,22 +129,57 @@ impl Int {
 
 #[derive(Debug)]
 pub struct Key {
-	init: Keycode,
-	value: Keycode,
+	init: config::Bindings,
+	value: config::Bindings,
 	selected: bool,
 	exists: bool,
 }
 
 impl Key {
-	pub fn new(value: Keycode) -> Self {
+	pub fn new(value: config::Bindings) -> Self {
+		Self { init: value.clone(), value, selected: false, exists: false }
+	}
+
+	pub fn value(&self) -> &config::Bindings {
+		&self.value
+	}
+
+	pub fn set_value(&mut self, value: config::Bindings) {
+		self.value = value;
+	}
+
+	pub fn focus(&mut self) {
+		self.selected = true;
+	}
+
+	pub fn exists(&mut self) {
+		self.exists = true;
+	}
+
+	pub fn unfocus(&mut self) {
+		self.selected = false;
+		self.exists = false;
+	}
+}
+
+#[derive(Debug)]
+pub struct Midi {
+	init: Option<midi::Control>,
+	value: Option<midi::Control>,
+	selected: bool,
+	exists: bool,
+}
+
+impl Midi {
+	pub fn new(value: Option<midi::Control>) -> Self {
 		Self { init: value, value, selected: false, exists: false }
 	}
 
-	pub fn value(&self) -> Keycode {
+	pub fn value(&self) -> Option<midi::Control> {
 		self.value
 	}
 
-	pub fn set_value(&mut self, value: Keycode) {
+	pub fn set_value(&mut self, value: Option<midi::Control>) {
 		self.value = value;
 	}
 
@@ -132,6 +195,10 @@ impl Key {
 		self.selected = false;
 		self.exists = false;
 	}
+
+	fn text(&self) -> String {
+		self.value.map_or_else(|| "---".to_string(), |c| c.to_string())
+	}
 }
 
 #[derive(Debug)]
@@ -154,6 +221,32 @@ impl Font {
 	}
 }
 
+/// One value out of a list, shown by its name.
+#[derive(Debug)]
+pub struct Choice {
+	names: &'static [&'static str],
+	init: usize,
+	value: usize,
+}
+
+impl Choice {
+	pub fn new(names: &'static [&'static str], value: usize) -> Self {
+		Self { names, init: value, value }
+	}
+
+	pub fn value(&self) -> usize {
+		self.value
+	}
+
+	pub fn set_value(&mut self, value: usize) {
+		self.value = value;
+	}
+
+	fn width(&self) -> usize {
+		self.names.iter().map(|name| name.len()).max().unwrap_or(0)
+	}
+}
+
 #[derive(Debug)]
 pub struct Rgb {
 	r: u8,
@@ -213,35 +306,57 @@ impl Device {
 	}
 }
 
+/// An audio capture or playback device. The first playback device is the default one.
 #[derive(Debug)]
 pub struct Audio {
 	list: Vec<String>,
 	selected: usize,
+	capture: bool,
+	subsystem: AudioSubsystem,
 }
 
 impl Audio {
-	pub fn new(device: Option<String>) -> Self {
-		let list = M8::list_capture_devices().unwrap_or_default();
-		let selected =
-			device.and_then(|ref dev| list.iter().position(|name| dev == name)).unwrap_or(0);
-		Self { list, selected }
+	pub fn new(subsystem: &AudioSubsystem, capture: bool, device: Option<String>) -> Self {
+		let mut audio = Self { list: vec![], selected: 0, capture, subsystem: subsystem.clone() };
+		audio.list = audio.devices();
+		audio.select(device.as_deref());
+		audio
 	}
 
+	/// Returns the device selected, `None` for the first M8 found or the default playback device.
 	pub fn value(&self) -> Option<&str> {
+		if self.selected == 0 {
+			return None;
+		}
 		if self.selected < self.list.len() {
 			return Some(&self.list[self.selected]);
 		}
 		None
 	}
 
+	pub fn select(&mut self, device: Option<&str>) {
+		self.selected =
+			device.and_then(|dev| self.list.iter().position(|name| dev == name)).unwrap_or(0);
+	}
+
+	fn devices(&self) -> Vec<String> {
+		if self.capture {
+			let mut list = vec!["AUTO".to_string()];
+			list.extend(audio::capture_devices(&self.subsystem));
+			list
+		} else {
+			let mut list = vec!["DEFAULT".to_string()];
+			list.extend(audio::playback_devices(&self.subsystem));
+			list
+		}
+	}
+
 	fn update_list(&mut self) {
-		if let Ok(list) = M8::list_capture_devices() {
-			if list != self.list {
-				self.list = list;
-				if self.selected >= self.list.len() {
-					self.selected = self.list.len() - 1;
-				}
-			}
+		let list = self.devices();
+		if list != self.list {
+			let device = self.value().map(|dev| dev.to_string());
+			self.list = list;
+			self.select(device.as_deref());
 		}
 	}
 }
@@ -254,8 +369,10 @@ pub enum Input {
 	Command2(Command, Command),
 	CommandLabel2(Command, String, usize, Command),
 	Key(Key),
+	Midi(Midi),
 	Rgb(Rgb),
 	Font(Font),
+	Choice(Choice),
 	Device(Device),
 	Audio(Audio),
 }
@@ -297,19 +414,18 @@ impl Item {
 			Item::Action2(..) => 2,
 			Item::Action3(..) => 3,
 			Item::Input(_, input) => match input {
-				Input::Bool(_) | Input::Int(_) | Input::Font(_) | Input::Command(_) => 1,
+				Input::Bool(_)
+				| Input::Int(_)
+				| Input::Font(_)
+				| Input::Choice(_)
+				| Input::Key(_)
+				| Input::Midi(_)
+				| Input::Command(_) => 1,
 				Input::Command2(..)
 				| Input::CommandLabel2(..)
 				| Input::Device(..)
 				| Input::Audio(..) => 2,
 				Input::Rgb(_) => 3,
-				Input::Key(Key { selected, .. }) => {
-					if *selected {
-						1
-					} else {
-						0
-					}
-				}
 			},
 			Item::Empty | Item::Text(_) | Item::Label(..) | Item::Label2(..) | Item::Title2(..) => {
 				0
@@ -417,18 +533,15 @@ impl Item {
 				},
 				Input::Command(c) => match edit {
 					Edit::Next(_) => {
-						if let Ok(cmd) = Command::try_from(*c as u8 + 1) {
+						if let Some(cmd) = c.next() {
 							*c = cmd;
 							return Action::Modified;
 						}
 					}
 					Edit::Prev(_) => {
-						let n = *c as u8;
-						if n > 0 {
-							if let Ok(cmd) = Command::try_from(n - 1) {
-								*c = cmd;
-								return Action::Modified;
-							}
+						if let Some(cmd) = c.prev() {
+							*c = cmd;
+							return Action::Modified;
 						}
 					}
 					Edit::Reset => {
@@ -442,12 +555,12 @@ impl Item {
 				Input::Command2(c1, c2) | Input::CommandLabel2(c1, _, _, c2) => match edit {
 					Edit::Next(_) => {
 						if cursor == 0 {
-							if let Ok(cmd) = Command::try_from(*c1 as u8 + 1) {
+							if let Some(cmd) = c1.next() {
 								*c1 = cmd;
 								return Action::Modified;
 							}
 						} else if cursor == 1 {
-							if let Ok(cmd) = Command::try_from(*c2 as u8 + 1) {
+							if let Some(cmd) = c2.next() {
 								*c2 = cmd;
 								return Action::Modified;
 							}
@@ -455,20 +568,14 @@ impl Item {
 					}
 					Edit::Prev(_) => {
 						if cursor == 0 {
-							let n = *c1 as u8;
-							if n > 0 {
-								if let Ok(cmd) = Command::try_from(n - 1) {
-									*c1 = cmd;
-									return Action::Modified;
-								}
+							if let Some(cmd) = c1.prev() {
+								*c1 = cmd;
+								return Action::Modified;
 							}
 						} else if cursor == 1 {
-							let n = *c2 as u8;
-							if n > 0 {
-								if let Ok(cmd) = Command::try_from(n - 1) {
-									*c2 = cmd;
-									return Action::Modified;
-								}
+							if let Some(cmd) = c2.prev() {
+								*c2 = cmd;
+								return Action::Modified;
 							}
 						}
 					}
@@ -488,12 +595,25 @@ impl Item {
 					Edit::Prev(_) => {}
 					Edit::Reset => {
 						if k.value != k.init {
-							k.value = k.init;
+							k.value = k.init.clone();
 							return Action::Modified;
 						}
 					}
 					Edit::Click => {
-						k.selected = true;
+						return Action::Do("ADD KEY");
+					}
+				},
+				Input::Midi(m) => match edit {
+					Edit::Next(_) => {}
+					Edit::Prev(_) => {}
+					Edit::Reset => {
+						if m.value != m.init {
+							m.value = m.init;
+							return Action::Modified;
+						}
+					}
+					Edit::Click => {
+						return Action::Do("LEARN ONE");
 					}
 				},
 				Input::Rgb(c) => {
@@ -583,6 +703,27 @@ impl Item {
 					}
 					Edit::Click => {}
 				},
+				Input::Choice(c) => match edit {
+					Edit::Next(_) => {
+						if c.value + 1 < c.names.len() {
+							c.value += 1;
+							return Action::Modified;
+						}
+					}
+					Edit::Prev(_) => {
+						if c.value > 0 {
+							c.value -= 1;
+							return Action::Modified;
+						}
+					}
+					Edit::Reset => {
+						if c.value != c.init {
+							c.value = c.init;
+							return Action::Modified;
+						}
+					}
+					Edit::Click => {}
+				},
 				Input::Device(d) => match edit {
 					Edit::Next(_) => {
 						if cursor == 0 && !d.list.is_empty() && d.selected + 1 < d.list.len() {
@@ -671,8 +812,7 @@ impl Item {
 			Item::Input(_, input) => match input {
 				Input::Bool(_) => (0, 0, font::width(3) as u32, LINE_HEIGHT as u32),
 				Input::Int(i) => {
-					let value = if i.value == 0 { 1 } else { i.value };
-					let width = font::width(((value as f64).log10() + 1.0).floor() as usize);
+					let width = font::width(i.text().len());
 					(0, 0, width as u32, LINE_HEIGHT as u32)
 				}
 				Input::Command(..) => {
@@ -705,7 +845,11 @@ impl Item {
 					}
 				}
 				Input::Key(k) if k.selected => (0, 0, font::width(12) as u32, LINE_HEIGHT as u32),
-				Input::Key(_) => (0, 0, 0, LINE_HEIGHT as u32),
+				Input::Key(k) => {
+					(0, 0, font::width(k.value.to_string().len()) as u32, LINE_HEIGHT as u32)
+				}
+				Input::Midi(m) if m.selected => (0, 0, font::width(13) as u32, LINE_HEIGHT as u32),
+				Input::Midi(m) => (0, 0, font::width(m.text().len()) as u32, LINE_HEIGHT as u32),
 				Input::Rgb(_) => {
 					let width = font::width(2);
 					if cursor == 0 {
@@ -721,6 +865,7 @@ impl Item {
 				Input::Font(_) => {
 					(0, 0, font::width(config::Font::MAX_LENGTH) as u32, LINE_HEIGHT as u32)
 				}
+				Input::Choice(c) => (0, 0, font::width(c.width()) as u32, LINE_HEIGHT as u32),
 				Input::Device(_) | Input::Audio(_) => {
 					let width = font::width(19);
 					if cursor == 0 {
@@ -794,8 +939,7 @@ impl Item {
 				}
 				Input::Int(i) => {
 					let fg = if cursor.is_some() { fg_screen } else { fg_value };
-					let s = format!("{}", i.value);
-					ctx.draw_str(&s, x, y, fg, fg)?;
+					ctx.draw_str(&i.text(), x, y, fg, fg)?;
 				}
 				Input::Command(c) => {
 					let fg = match cursor {
@@ -845,6 +989,18 @@ impl Item {
 						ctx.draw_str(&s, x, y, fg, fg)?;
 					}
 				}
+				Input::Midi(m) => {
+					let fg = if cursor.is_some() { fg_screen } else { fg_value };
+					if m.selected {
+						if m.exists {
+							ctx.draw_str("CONTROL TAKEN", x, y, fg_info, fg_info)?;
+						} else {
+							ctx.draw_str("PRESS CONTROL", x, y, fg, fg)?;
+						}
+					} else {
+						ctx.draw_str(&m.text(), x, y, fg, fg)?;
+					}
+				}
 				Input::Rgb(c) => {
 					let (fg1, fg2, fg3) = match cursor {
 						Some(0) => (fg_screen, fg_value, fg_value),
@@ -871,6 +1027,10 @@ impl Item {
 					let s = format!("{}", f.value);
 					ctx.draw_str(&s, x, y, fg, fg)?;
 				}
+				Input::Choice(c) => {
+					let fg = if cursor.is_some() { fg_screen } else { fg_value };
+					ctx.draw_str(c.names[c.value], x, y, fg, fg)?;
+				}
 				Input::Device(d) => {
 					let width = font::width(19);
 					let (fg1, fg2) = match cursor {
@@ -889,8 +1049,12 @@ impl Item {
 						Some(1) => (fg_value, fg_screen),
 						_ => (fg_value, fg_value),
 					};
-					let dev = d.value().unwrap_or("");
-					ctx.draw_str(dev, x, y, fg1, fg1)?;
+					let dev: String = d
+						.list
+						.get(d.selected)
+						.map(|s| s.chars().take(18).collect())
+						.unwrap_or_default();
+					ctx.draw_str(&dev, x, y, fg1, fg1)?;
 					ctx.draw_str("REFRESH", x + width, y, fg2, fg2)?;
 				}
 			},
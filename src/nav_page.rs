@@ -1,11 +1,14 @@
-use sdl2::keyboard::Keycode;
+use sdl2::AudioSubsystem;
 use std::cmp::Ordering;
 
 use crate::{
 	config::{self, Command},
 	draw::{Context, LINE_HEIGHT},
-	font,
-	nav_item::{Action, Audio, Bool, Device, Direction, Edit, Font, Input, Int, Item, Key, Rgb},
+	font, midi,
+	nav_item::{
+		Action, Audio, Bool, Choice, Device, Direction, Edit, Font, Input, Int, Item, Key, Midi,
+		Rgb,
+	},
 };
 
 const PAD_X: i32 = 10;
@@ -125,6 +128,17 @@ impl Page {
 		}
 	}
 
+	/// Returns the index of the item under the cursor.
+	pub fn cursor_item(&self) -> usize {
+		self.cursor.1
+	}
+
+	pub fn set_cursor(&mut self, item: usize) {
+		if item < self.items.len() && self.items[item].cursors() > 0 {
+			self.cursor = (0, item);
+		}
+	}
+
 	fn reset_cursor_x(&mut self) {
 		let cursors = self.item().cursors();
 		if cursors > 0 && self.cursor.0 > cursors - 1 {
@@ -250,6 +264,17 @@ impl Page {
 		self.add_item(Item::Input(label.into(), Input::Int(Int::new(value, min, max, step))))
 	}
 
+	pub fn add_signed_int<I: Into<String>>(
+		&mut self,
+		label: I,
+		value: isize,
+		min: isize,
+		max: isize,
+		step: usize,
+	) {
+		self.add_item(Item::Input(label.into(), Input::Int(Int::signed(value, min, max, step))))
+	}
+
 	pub fn add_empty(&mut self) {
 		self.add_item(Item::Empty)
 	}
@@ -266,6 +291,16 @@ impl Page {
 		self.add_item(Item::Input(label.into(), Input::Font(Font::new(value))))
 	}
 
+	/// Adds a choice between `names`, `value` being the index of the selected one.
+	pub fn add_choice<I: Into<String>>(
+		&mut self,
+		label: I,
+		names: &'static [&'static str],
+		value: usize,
+	) {
+		self.add_item(Item::Input(label.into(), Input::Choice(Choice::new(names, value))))
+	}
+
 	pub fn add_action2(&mut self, action1: &'static str, action2: &'static str) {
 		self.add_item(Item::Action2(action1, action2))
 	}
@@ -283,10 +318,14 @@ impl Page {
 		self.add_item(Item::Input(label.into(), Input::Rgb(Rgb::new(rgb))))
 	}
 
-	pub fn add_key<I: Into<String>>(&mut self, label: I, value: Keycode) {
+	pub fn add_key<I: Into<String>>(&mut self, label: I, value: config::Bindings) {
 		self.add_item(Item::Input(label.into(), Input::Key(Key::new(value))))
 	}
 
+	pub fn add_midi<I: Into<String>>(&mut self, label: I, value: Option<midi::Control>) {
+		self.add_item(Item::Input(label.into(), Input::Midi(Midi::new(value))))
+	}
+
 	pub fn add_info<I: Into<String>>(&mut self, label: I, info: I) {
 		self.add_item(Item::Label2(label.into(), info.into()))
 	}
@@ -321,7 +360,16 @@ impl Page {
 		self.add_item(Item::Input(label.into(), Input::Device(Device::new(device))))
 	}
 
-	pub fn add_audio<I: Into<String>>(&mut self, label: I, device: Option<String>) {
-		self.add_item(Item::Input(label.into(), Input::Audio(Audio::new(device))))
+	pub fn add_audio<I: Into<String>>(
+		&mut self,
+		label: I,
+		subsystem: &AudioSubsystem,
+		capture: bool,
+		device: Option<String>,
+	) {
+		self.add_item(Item::Input(
+			label.into(),
+			Input::Audio(Audio::new(subsystem, capture, device)),
+		))
 	}
 }
@@ -0,0 +1,290 @@
+use std::{
+	cell::RefCell,
+	collections::VecDeque,
+	fs::File,
+	io::{self, BufReader, BufWriter, Read, Write},
+	rc::Rc,
+	time::{Duration, Instant},
+};
+
+use crate::{slip, transport::Transport};
+
+const MAGIC: &[u8; 8] = b"RM8REC01";
+const MIN_SPEED: f64 = 0.25;
+const MAX_SPEED: f64 = 8.0;
+pub const SEEK_STEP: Duration = Duration::from_secs(5);
+
+/// Writes every decoded SLIP frame to a file, prefixed with the time elapsed since the start of
+/// the recording (u64 microseconds) and the frame length (u16), both little endian.
+pub struct Recorder {
+	writer: BufWriter<File>,
+	start: Instant,
+}
+
+impl Recorder {
+	pub fn create(file: &str) -> io::Result<Self> {
+		let mut writer = BufWriter::new(File::create(file)?);
+		writer.write_all(MAGIC)?;
+		Ok(Self { writer, start: Instant::now() })
+	}
+
+	pub fn record(&mut self, frame: &[u8]) -> io::Result<()> {
+		let micros = self.start.elapsed().as_micros() as u64;
+		self.writer.write_all(&micros.to_le_bytes())?;
+		self.writer.write_all(&(frame.len() as u16).to_le_bytes())?;
+		self.writer.write_all(frame)
+	}
+}
+
+impl Drop for Recorder {
+	fn drop(&mut self) {
+		let _ = self.writer.flush();
+	}
+}
+
+struct Frame {
+	time: u64,
+	data: Vec<u8>,
+}
+
+struct State {
+	frames: Vec<Frame>,
+	pos: usize,
+	clock: u64,
+	ticks: Instant,
+	speed: f64,
+	paused: bool,
+}
+
+impl State {
+	fn duration(&self) -> u64 {
+		self.frames.last().map(|f| f.time).unwrap_or(0)
+	}
+
+	fn advance(&mut self) {
+		let now = Instant::now();
+		if !self.paused {
+			let elapsed = (now - self.ticks).as_micros() as f64 * self.speed;
+			self.clock = (self.clock + elapsed as u64).min(self.duration());
+		}
+		self.ticks = now;
+	}
+}
+
+/// Plays back a file written by `Recorder`, at the original timing.
+///
+/// `transport` gives the M8 end of the replay, this handle keeps control over it.
+#[derive(Clone)]
+pub struct Replay {
+	state: Rc<RefCell<State>>,
+	name: String,
+}
+
+impl Replay {
+	pub fn open(file: &str) -> io::Result<Self> {
+		let mut reader = BufReader::new(File::open(file)?);
+		let mut magic = [0; 8];
+		reader.read_exact(&mut magic)?;
+		if &magic != MAGIC {
+			return Err(io::Error::new(io::ErrorKind::InvalidData, "not a rm8 recording"));
+		}
+		let mut frames = Vec::new();
+		let mut header = [0; 10];
+		loop {
+			match reader.read_exact(&mut header) {
+				Ok(()) => {}
+				Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => break,
+				Err(e) => return Err(e),
+			}
+			let time = u64::from_le_bytes(header[..8].try_into().unwrap());
+			let len = u16::from_le_bytes(header[8..].try_into().unwrap());
+			let mut data = vec![0; len as usize];
+			reader.read_exact(&mut data)?;
+			frames.push(Frame { time, data });
+		}
+		Ok(Self {
+			state: Rc::new(RefCell::new(State {
+				frames,
+				pos: 0,
+				clock: 0,
+				ticks: Instant::now(),
+				speed: 1.0,
+				paused: false,
+			})),
+			name: file.to_string(),
+		})
+	}
+
+	pub fn transport(&self) -> Box<dyn Transport> {
+		Box::new(ReplayTransport { replay: self.clone(), pending: VecDeque::new() })
+	}
+
+	pub fn toggle_pause(&mut self) {
+		let mut state = self.state.borrow_mut();
+		state.advance();
+		state.paused = !state.paused;
+	}
+
+	pub fn faster(&mut self) {
+		let mut state = self.state.borrow_mut();
+		state.advance();
+		state.speed = (state.speed * 2.0).min(MAX_SPEED);
+	}
+
+	pub fn slower(&mut self) {
+		let mut state = self.state.borrow_mut();
+		state.advance();
+		state.speed = (state.speed / 2.0).max(MIN_SPEED);
+	}
+
+	pub fn forward(&mut self, step: Duration) {
+		let mut state = self.state.borrow_mut();
+		state.advance();
+		state.clock = (state.clock + step.as_micros() as u64).min(state.duration());
+	}
+
+	/// Going back in time replays every frame from the start, so that the screen is rebuilt.
+	pub fn rewind(&mut self, step: Duration) {
+		let mut state = self.state.borrow_mut();
+		state.advance();
+		state.clock = state.clock.saturating_sub(step.as_micros() as u64);
+		state.pos = 0;
+	}
+
+	pub fn status(&self) -> String {
+		let state = self.state.borrow();
+		format!(
+			"rm8 - replay {} {} / {} x{}{}",
+			self.name,
+			format_time(state.clock),
+			format_time(state.duration()),
+			state.speed,
+			if state.paused { " (paused)" } else { "" },
+		)
+	}
+}
+
+fn format_time(micros: u64) -> String {
+	let secs = micros / 1_000_000;
+	format!("{:02}:{:02}", secs / 60, secs % 60)
+}
+
+struct ReplayTransport {
+	replay: Replay,
+	pending: VecDeque<u8>,
+}
+
+impl Transport for ReplayTransport {
+	fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+		let mut state = self.replay.state.borrow_mut();
+		state.advance();
+		let mut encoded = Vec::new();
+		while state.pos < state.frames.len() && state.frames[state.pos].time <= state.clock {
+			slip::encode(&state.frames[state.pos].data, &mut encoded);
+			state.pos += 1;
+		}
+		self.pending.extend(encoded);
+		let n = buf.len().min(self.pending.len());
+		for (dst, src) in buf.iter_mut().zip(self.pending.drain(..n)) {
+			*dst = src;
+		}
+		Ok(n)
+	}
+
+	fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+		Ok(buf.len())
+	}
+
+	fn name(&self) -> Option<String> {
+		Some(format!("replay:{}", self.replay.name))
+	}
+
+	fn close(&mut self) -> io::Result<()> {
+		Ok(())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::slip::Slip;
+
+	/// Records the frames to a file of its own and opens it for replay, paused at the start.
+	fn replay_of(name: &str, frames: &[&[u8]]) -> Replay {
+		let file = std::env::temp_dir().join(format!("rm8-{}-{}.rec", name, std::process::id()));
+		let file = file.to_str().unwrap();
+		let mut recorder = Recorder::create(file).unwrap();
+		for frame in frames {
+			recorder.record(frame).unwrap();
+		}
+		drop(recorder);
+		let mut replay = Replay::open(file).unwrap();
+		std::fs::remove_file(file).unwrap();
+		replay.toggle_pause();
+		// the clock ran from the opening to the pause
+		replay.state.borrow_mut().clock = 0;
+		replay
+	}
+
+	#[test]
+	fn replays_the_frames_recorded() {
+		let frames: [&[u8]; 3] = [&[0xfb, 1, 0], &[0xfe, 1, 0, 2, 0, 0xc0, 0xdb, 7], &[0xff; 6]];
+		let mut replay = replay_of("frames", &frames);
+		{
+			let state = replay.state.borrow();
+			assert_eq!(state.frames.len(), 3);
+			assert!(state.frames.windows(2).all(|f| f[0].time <= f[1].time));
+			for (read, recorded) in state.frames.iter().zip(frames) {
+				assert_eq!(read.data, recorded);
+			}
+		}
+		replay.forward(SEEK_STEP);
+		let mut transport = replay.transport();
+		let mut slip = Slip::<64>::new();
+		let mut buf = [0; 16];
+		for frame in frames {
+			let read = slip.read(transport.as_mut(), &mut buf).unwrap();
+			assert_eq!(read, Some(frame));
+		}
+		assert_eq!(slip.read(transport.as_mut(), &mut buf).unwrap(), None);
+	}
+
+	#[test]
+	fn rejects_other_files() {
+		let file = std::env::temp_dir().join(format!("rm8-other-{}.rec", std::process::id()));
+		std::fs::write(&file, b"RIFF....WAVE").unwrap();
+		let result = Replay::open(file.to_str().unwrap());
+		std::fs::remove_file(&file).unwrap();
+		assert_eq!(result.err().unwrap().kind(), io::ErrorKind::InvalidData);
+	}
+
+	#[test]
+	fn seeks_within_the_recording() {
+		let mut replay = replay_of("seek", &[&[0xfb, 1, 0], &[0xfb, 2, 0]]);
+		replay.state.borrow_mut().frames[1].time = 12_000_000;
+		replay.forward(SEEK_STEP);
+		assert_eq!(replay.state.borrow().clock, 5_000_000);
+		replay.forward(SEEK_STEP * 3);
+		assert_eq!(replay.state.borrow().clock, 12_000_000);
+		replay.rewind(SEEK_STEP);
+		assert_eq!(replay.state.borrow().clock, 7_000_000);
+		replay.rewind(SEEK_STEP * 3);
+		let state = replay.state.borrow();
+		// the screen is rebuilt from the first frame
+		assert_eq!((state.clock, state.pos), (0, 0));
+	}
+
+	#[test]
+	fn keeps_the_speed_within_its_limits() {
+		let mut replay = replay_of("speed", &[&[0xfb, 1, 0]]);
+		for _ in 0..10 {
+			replay.faster();
+		}
+		assert_eq!(replay.state.borrow().speed, MAX_SPEED);
+		for _ in 0..10 {
+			replay.slower();
+		}
+		assert_eq!(replay.state.borrow().speed, MIN_SPEED);
+		assert!(replay.status().ends_with("x0.25 (paused)"));
+	}
+}
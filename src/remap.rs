@@ -1,48 +1,117 @@
-use crate::nav::{Input, Item, Navigation};
-use sdl2::keyboard::Keycode;
+use crate::{
+	config::{Binding, Bindings, Command, Config},
+	menu_tools,
+	nav::{Input, Item, Navigation},
+};
 
+/// Binds the keys of a page again: all of them in turn, one key each, or one more key for the
+/// item under the cursor.
 pub struct Remap {
 	pos: usize,
-	keys: Vec<Keycode>,
+	end: usize,
+	add: bool,
+	/// A modifier pressed alone, bound if it is released before any other key.
+	modifier: Option<Binding>,
+	cursor: usize,
+	keys: Vec<Bindings>,
+	commands: &'static [(Command, &'static str)],
+	/// The keys of the commands of the other pages.
+	others: Vec<Bindings>,
 }
 
 impl Remap {
-	pub fn new(menu: &mut Navigation) -> Self {
+	pub fn new(menu: &mut Navigation, config: &Config) -> Self {
+		Self::start(menu, config, 0, false)
+	}
+
+	pub fn add(menu: &mut Navigation, config: &Config) -> Self {
+		let pos = menu.page().cursor_item();
+		Self::start(menu, config, pos, true)
+	}
+
+	fn start(menu: &mut Navigation, config: &Config, pos: usize, add: bool) -> Self {
+		let cursor = menu.page().cursor_item();
+		let commands = menu_tools::page_commands(menu.page());
+		let others = config
+			.key_bindings()
+			.into_iter()
+			.filter(|(_, cmd)| !commands.iter().any(|(c, _)| c == cmd))
+			.map(|(b, _)| b.clone())
+			.collect();
 		let mut keys = Vec::new();
 		for (i, item) in menu.page_mut().items_mut().enumerate() {
 			if let Item::Input(_, Input::Key(k)) = item {
-				if i == 0 {
+				if i == pos {
 					k.focus();
 				}
-				keys.push(k.value());
+				keys.push(k.value().clone());
 			}
 		}
-		menu.page_mut().reset_cursor();
+		let end = if add { pos + 1 } else { keys.len() };
+		menu.page_mut().set_cursor(pos);
 		menu.dirty();
-		Self { pos: usize::MAX, keys }
+		Self { pos, end, add, modifier: None, cursor, keys, commands, others }
 	}
 
-	pub fn remap(&mut self, menu: &mut Navigation, key: Keycode) -> bool {
-		if self.pos == usize::MAX {
-			self.pos = 0;
-		} else {
+	/// Returns the command whose key is being bound.
+	pub fn command(&self) -> Option<Command> {
+		self.commands.get(self.pos).map(|(cmd, _)| *cmd)
+	}
+
+	/// Takes a key pressed or released, returns true once all the keys are bound. A key is bound
+	/// with the modifiers held when it is pressed, a modifier alone when it is released.
+	pub fn key(&mut self, menu: &mut Navigation, binding: Binding, released: bool) -> bool {
+		let binding = match (binding.is_modifier(), released) {
+			(true, false) => {
+				self.modifier = Some(binding);
+				return false;
+			}
+			// the keys pressed before the remapping started are not taken when released
+			(true, true) => match self.modifier.take() {
+				Some(modifier) if modifier.key == binding.key => modifier,
+				_ => return false,
+			},
+			(false, false) => {
+				self.modifier = None;
+				binding
+			}
+			(false, true) => return false,
+		};
+		self.remap(menu, binding)
+	}
+
+	fn remap(&mut self, menu: &mut Navigation, binding: Binding) -> bool {
+		let taken = self
+			.keys
+			.iter()
+			.enumerate()
+			.any(|(i, b)| i != self.pos && (self.add || i < self.pos) && b.contains(&binding))
+			|| self.others.iter().any(|b| b.contains(&binding));
+		{
 			let mut items = menu.page_mut().items_mut().skip(self.pos);
 			if let Some(Item::Input(_, Input::Key(k))) = items.next() {
-				if self.keys[..self.pos].contains(&key) {
+				if taken {
 					k.exists();
 				} else {
+					if self.add {
+						self.keys[self.pos].add(binding);
+					} else {
+						self.keys[self.pos] = binding.into();
+					}
 					k.unfocus();
-					k.set_value(key);
+					k.set_value(self.keys[self.pos].clone());
 					self.pos += 1;
-					if let Some(Item::Input(_, Input::Key(k))) = items.next() {
-						k.focus();
+					if self.pos < self.end {
+						if let Some(Item::Input(_, Input::Key(k))) = items.next() {
+							k.focus();
+						}
 					}
 				}
 			}
 		}
-		menu.page_mut().reset_cursor();
+		menu.page_mut().set_cursor(self.pos);
 		menu.dirty();
-		self.pos >= self.keys.len()
+		self.pos >= self.end
 	}
 
 	pub fn abort(&mut self, menu: &mut Navigation) {
@@ -51,7 +120,66 @@ impl Remap {
 				k.unfocus();
 			}
 		}
-		menu.page_mut().reset_cursor();
+		menu.page_mut().set_cursor(self.cursor);
 		menu.dirty();
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::nav::Page;
+	use sdl2::keyboard::{Keycode, Mod};
+
+	fn keys(menu: &mut Navigation) -> Vec<Bindings> {
+		let mut keys = Vec::new();
+		for item in menu.page_mut().items_mut() {
+			if let Item::Input(_, Input::Key(k)) = item {
+				keys.push(k.value().clone());
+			}
+		}
+		keys
+	}
+
+	#[test]
+	fn binds_keys_with_the_modifiers_held_when_pressed() {
+		let mut page = Page::new("KEYS", 'K');
+		page.add_key("ONE", Keycode::A.into());
+		page.add_key("TWO", Keycode::B.into());
+		let mut menu = Navigation::new();
+		menu.add_page(page);
+		let mut remap = Remap::new(&mut menu, &Config::default());
+		let key = |key, keymod| Binding::new(key, keymod);
+		// the key which started the remapping is released
+		assert!(!remap.key(&mut menu, key(Keycode::Return, Mod::NOMOD), true));
+		assert!(!remap.key(&mut menu, key(Keycode::LCtrl, Mod::LCTRLMOD), false));
+		assert!(!remap.key(&mut menu, key(Keycode::S, Mod::LCTRLMOD), false));
+		// releasing the modifier first binds nothing more
+		assert!(!remap.key(&mut menu, key(Keycode::LCtrl, Mod::NOMOD), true));
+		assert!(!remap.key(&mut menu, key(Keycode::S, Mod::NOMOD), true));
+		assert!(!remap.key(&mut menu, key(Keycode::LShift, Mod::LSHIFTMOD), false));
+		assert!(remap.key(&mut menu, key(Keycode::LShift, Mod::NOMOD), true));
+		let ctrl_s = key(Keycode::S, Mod::LCTRLMOD);
+		assert_eq!(keys(&mut menu), [ctrl_s.into(), Bindings::from(Keycode::LShift)]);
+	}
+
+	#[test]
+	fn refuses_the_keys_of_the_other_pages() {
+		let config = Config::default();
+		let mut page = Page::new("APP KEYS", 'P');
+		for name in ["DIAGNOSTIC", "MACRO REC", "MACRO PLAY", "CONFIG", "ESCAPE"] {
+			page.add_key(name, Keycode::A.into());
+		}
+		let mut menu = Navigation::new();
+		menu.add_page(page);
+		menu.page_mut().set_cursor(4);
+		let mut remap = Remap::add(&mut menu, &config);
+		assert_eq!(remap.command(), Some(Command::Escape));
+		// F12 takes screenshots, on the RM8 KEYS page
+		assert!(!remap.key(&mut menu, Binding::from(Keycode::F12), false));
+		assert!(remap.key(&mut menu, Binding::from(Keycode::Q), false));
+		let mut expected = Bindings::from(Keycode::A);
+		expected.add(Keycode::Q.into());
+		assert_eq!(keys(&mut menu)[4], expected);
+	}
+}
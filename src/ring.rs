@@ -0,0 +1,129 @@
+use std::sync::{
+	atomic::{AtomicI16, AtomicUsize, Ordering},
+	Arc,
+};
+
+struct Buffer {
+	samples: Box<[AtomicI16]>,
+	read: AtomicUsize,
+	write: AtomicUsize,
+}
+
+impl Buffer {
+	fn len(&self) -> usize {
+		let write = self.write.load(Ordering::Acquire);
+		let read = self.read.load(Ordering::Acquire);
+		write.wrapping_sub(read)
+	}
+}
+
+/// Creates a single producer, single consumer ring buffer of samples.
+///
+/// Neither side ever blocks or allocates, so both can be used from audio callbacks.
+pub fn ring(capacity: usize) -> (Producer, Consumer) {
+	let buffer = Arc::new(Buffer {
+		samples: (0..capacity.max(1)).map(|_| AtomicI16::new(0)).collect(),
+		read: AtomicUsize::new(0),
+		write: AtomicUsize::new(0),
+	});
+	(Producer { buffer: buffer.clone() }, Consumer { buffer })
+}
+
+pub struct Producer {
+	buffer: Arc<Buffer>,
+}
+
+impl Producer {
+	/// Pushes all the samples, or none of them if there is not enough room (so that
+	/// interleaved channels stay aligned). Returns false in the latter case.
+	pub fn push(&mut self, samples: &[i16]) -> bool {
+		let buffer = &self.buffer;
+		let capacity = buffer.samples.len();
+		if samples.len() > capacity - buffer.len() {
+			return false;
+		}
+		let write = buffer.write.load(Ordering::Relaxed);
+		for (i, &sample) in samples.iter().enumerate() {
+			buffer.samples[write.wrapping_add(i) % capacity].store(sample, Ordering::Relaxed);
+		}
+		buffer.write.store(write.wrapping_add(samples.len()), Ordering::Release);
+		true
+	}
+}
+
+pub struct Consumer {
+	buffer: Arc<Buffer>,
+}
+
+impl Consumer {
+	/// Returns the number of samples waiting to be popped.
+	pub fn available(&self) -> usize {
+		self.buffer.len()
+	}
+
+	/// Pops as many samples as available into `out` and returns how many were popped.
+	pub fn pop(&mut self, out: &mut [i16]) -> usize {
+		let buffer = &self.buffer;
+		let capacity = buffer.samples.len();
+		let n = out.len().min(buffer.len());
+		let read = buffer.read.load(Ordering::Relaxed);
+		for (i, sample) in out[..n].iter_mut().enumerate() {
+			*sample = buffer.samples[read.wrapping_add(i) % capacity].load(Ordering::Relaxed);
+		}
+		buffer.read.store(read.wrapping_add(n), Ordering::Release);
+		n
+	}
+
+	/// Drops up to `n` of the oldest samples.
+	pub fn skip(&mut self, n: usize) {
+		let buffer = &self.buffer;
+		let n = n.min(buffer.len());
+		let read = buffer.read.load(Ordering::Relaxed);
+		buffer.read.store(read.wrapping_add(n), Ordering::Release);
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn wraps_around_the_end_of_the_buffer() {
+		let (mut producer, mut consumer) = ring(4);
+		let mut out = [0; 4];
+		for i in 0..10 {
+			assert!(producer.push(&[i, -i, i * 2]));
+			assert_eq!(consumer.available(), 3);
+			assert_eq!(consumer.pop(&mut out), 3);
+			assert_eq!(out[..3], [i, -i, i * 2]);
+		}
+		assert_eq!(consumer.pop(&mut out), 0);
+	}
+
+	#[test]
+	fn refuses_the_samples_that_do_not_fit() {
+		let (mut producer, mut consumer) = ring(4);
+		assert!(producer.push(&[1, 2, 3]));
+		assert!(!producer.push(&[4, 5]));
+		assert_eq!(consumer.available(), 3);
+		assert!(producer.push(&[4]));
+		assert!(!producer.push(&[5]));
+		let mut out = [0; 8];
+		assert_eq!(consumer.pop(&mut out), 4);
+		assert_eq!(out[..4], [1, 2, 3, 4]);
+	}
+
+	#[test]
+	fn skips_the_oldest_samples() {
+		let (mut producer, mut consumer) = ring(4);
+		assert!(producer.push(&[1, 2, 3]));
+		consumer.skip(2);
+		assert!(producer.push(&[4, 5]));
+		consumer.skip(1);
+		let mut out = [0; 4];
+		assert_eq!(consumer.pop(&mut out), 2);
+		assert_eq!(out[..2], [4, 5]);
+		consumer.skip(10);
+		assert_eq!(consumer.available(), 0);
+	}
+}
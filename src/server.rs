@@ -0,0 +1,208 @@
+use std::{
+	io::{self, Read, Write},
+	net::{Shutdown, TcpListener, TcpStream},
+	sync::{mpsc, Arc, Mutex},
+	thread,
+};
+
+use crate::{config::Font, m8, slip, web};
+
+pub type Clients = Arc<Mutex<ClientList>>;
+
+/// The queues of the frames sent to the clients.
+#[derive(Default)]
+pub struct ClientList {
+	senders: Vec<mpsc::SyncSender<Arc<[u8]>>>,
+	/// The last system info frame, sent first to the new clients as the M8 only sends it when
+	/// the display is enabled.
+	system_info: Option<Arc<[u8]>>,
+}
+
+/// Frames queued for a client before it is considered too slow and dropped.
+const CLIENT_QUEUE: usize = 1024;
+
+/// Shares the display of an M8 over the network: every frame read from the M8 is sent SLIP
+/// encoded to the connected clients, and the `C`, `K` and `R` commands they send back are
+/// forwarded to the M8.
+///
+/// Another rm8 connects to a TCP listener with `-connect HOST:PORT` (or `-dev tcp:HOST:PORT`),
+/// a browser to an HTTP listener.
+pub struct Server {
+	clients: Clients,
+	sender: mpsc::Sender<Vec<u8>>,
+	input: mpsc::Receiver<Vec<u8>>,
+	frame: Vec<u8>,
+}
+
+impl Server {
+	pub fn new() -> Self {
+		let (sender, input) = mpsc::channel();
+		Self { clients: Clients::default(), sender, input, frame: Vec::new() }
+	}
+
+	/// Accepts rm8 clients on `addr`, a port alone meaning the loopback interface.
+	pub fn listen(&self, addr: &str) -> io::Result<()> {
+		let listener = bind(addr)?;
+		eprintln!("serving the M8 display on {}", listener.local_addr()?);
+		let (clients, input) = (self.clients.clone(), self.sender.clone());
+		thread::spawn(move || {
+			for stream in listener.incoming().flatten() {
+				if let Err(e) = add_client(stream, &clients, input.clone()) {
+					eprintln!("client rejected: {}", e);
+				}
+			}
+		});
+		Ok(())
+	}
+
+	/// Serves the web viewer on `addr`, a port alone meaning the loopback interface. The input of
+	/// the browsers is ignored unless `allow_input` is set.
+	pub fn listen_http(&self, addr: &str, font: Font, allow_input: bool) -> io::Result<()> {
+		let listener = bind(addr)?;
+		eprintln!("serving the web viewer on http://{}", listener.local_addr()?);
+		web::spawn(listener, self.clients.clone(), self.sender.clone(), allow_input, font);
+		Ok(())
+	}
+
+	/// Sends a decoded frame to all the clients, forgetting the ones that are gone or that cannot
+	/// keep up: a client missing frames would show a broken display anyway.
+	pub fn broadcast(&mut self, frame: &[u8]) {
+		if let Ok(mut clients) = self.clients.lock() {
+			let system_info = frame.first() == Some(&m8::SYSTEM_INFO_CMD);
+			if clients.senders.is_empty() && !system_info {
+				return;
+			}
+			self.frame.clear();
+			slip::encode(frame, &mut self.frame);
+			let frame: Arc<[u8]> = self.frame.as_slice().into();
+			if system_info {
+				clients.system_info = Some(frame.clone());
+			}
+			clients.senders.retain(|client| match client.try_send(frame.clone()) {
+				Ok(()) => true,
+				Err(mpsc::TrySendError::Full(_)) => {
+					eprintln!("client dropped: too slow");
+					false
+				}
+				Err(mpsc::TrySendError::Disconnected(_)) => false,
+			});
+		}
+	}
+
+	/// Returns the commands received from the clients since the last call.
+	pub fn input(&self) -> Vec<Vec<u8>> {
+		self.input.try_iter().collect()
+	}
+}
+
+fn bind(addr: &str) -> io::Result<TcpListener> {
+	if addr.chars().all(|c| c.is_ascii_digit()) {
+		let port =
+			addr.parse::<u16>().map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+		TcpListener::bind(("127.0.0.1", port))
+	} else {
+		TcpListener::bind(addr)
+	}
+}
+
+fn add_client(
+	stream: TcpStream,
+	clients: &Clients,
+	input: mpsc::Sender<Vec<u8>>,
+) -> io::Result<()> {
+	let peer = stream.peer_addr()?;
+	stream.set_nodelay(true)?;
+	let reader = stream.try_clone()?;
+	add_stream(stream, clients)?;
+	thread::spawn(move || {
+		forward_input(reader, &input);
+		eprintln!("client {} disconnected", peer);
+	});
+	eprintln!("client {} connected", peer);
+	Ok(())
+}
+
+/// Sends the last system info then the frames broadcast from now on to `stream`, on a thread of
+/// its own.
+pub fn add_stream(stream: TcpStream, clients: &Clients) -> io::Result<()> {
+	let mut writer = stream;
+	let (sender, receiver) = mpsc::sync_channel::<Arc<[u8]>>(CLIENT_QUEUE);
+	thread::spawn(move || {
+		let mut buf = Vec::new();
+		while let Ok(frame) = receiver.recv() {
+			// send whatever piled up while the previous write was blocked in one go
+			buf.clear();
+			buf.extend_from_slice(&frame);
+			for frame in receiver.try_iter() {
+				buf.extend_from_slice(&frame);
+			}
+			if writer.write_all(&buf).is_err() {
+				break;
+			}
+		}
+		let _ = writer.shutdown(Shutdown::Both);
+	});
+	let mut clients = clients.lock().map_err(|_| io::Error::from(io::ErrorKind::Other))?;
+	if let Some(ref system_info) = clients.system_info {
+		// the queue is empty yet
+		let _ = sender.try_send(system_info.clone());
+	}
+	clients.senders.push(sender);
+	Ok(())
+}
+
+/// Splits what is read from `reader` into commands and sends them to `input`, until the end of
+/// the stream.
+pub fn forward_input<R: Read>(reader: R, input: &mpsc::Sender<Vec<u8>>) {
+	let mut reader = reader;
+	let mut pending = Vec::new();
+	let mut buf = [0; 64];
+	loop {
+		match reader.read(&mut buf) {
+			Ok(0) => break,
+			Ok(n) => pending.extend_from_slice(&buf[..n]),
+			Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+			Err(_) => break,
+		}
+		while let Some(len) = command_len(&pending) {
+			let command: Vec<u8> = pending.drain(..len).collect();
+			if input.send(command).is_err() {
+				return;
+			}
+		}
+	}
+}
+
+/// Returns the length of the command at the start of `input` once it is complete.
+fn command_len(input: &[u8]) -> Option<usize> {
+	match input {
+		[] | [b'C'] | [b'K'] => None,
+		[b'C', ..] | [b'K', 255, ..] => Some(2),
+		[b'K', _] => None,
+		[b'K', ..] => Some(3),
+		_ => Some(1),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn sends_the_system_info_to_the_new_clients() {
+		let mut server = Server::new();
+		let info = [m8::SYSTEM_INFO_CMD, 2, 1, 4, 0, 0];
+		server.broadcast(&info);
+		server.broadcast(&[m8::JOYPAD_CMD, 0, 0]);
+		let listener = TcpListener::bind(("127.0.0.1", 0)).unwrap();
+		let mut client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+		add_stream(listener.accept().unwrap().0, &server.clients).unwrap();
+		server.broadcast(&[m8::JOYPAD_CMD, 1, 0]);
+		let mut expected = Vec::new();
+		slip::encode(&info, &mut expected);
+		slip::encode(&[m8::JOYPAD_CMD, 1, 0], &mut expected);
+		let mut received = vec![0; expected.len()];
+		client.read_exact(&mut received).unwrap();
+		assert_eq!(received, expected);
+	}
+}
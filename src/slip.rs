@@ -1,6 +1,6 @@
 use std::io;
 
-use serialport::SerialPort;
+use crate::transport::Transport;
 
 const END: u8 = 0xc0;
 const ESC: u8 = 0xdb;
@@ -10,6 +10,21 @@ const ESC_ESC: u8 = 0xdd;
 enum SlipState {
 	Normal,
 	Escape,
+	/// Skipping the rest of a bad frame, up to the next `END`.
+	Discard,
+}
+
+/// Counters of the decoder, see `Slip::stats`.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Stats {
+	/// Bytes read.
+	pub bytes: u64,
+	/// Frames decoded.
+	pub frames: u64,
+	/// Frames dropped because they were too short or had an invalid escape sequence.
+	pub malformed: u64,
+	/// Frames dropped because they did not fit in the buffer.
+	pub overflows: u64,
 }
 
 pub struct Slip<const N: usize> {
@@ -18,34 +33,59 @@ pub struct Slip<const N: usize> {
 	rmax: usize,
 	rpos: usize,
 	wpos: usize,
+	stats: Stats,
 }
 
 impl<const N: usize> Slip<N> {
 	pub fn new() -> Self {
-		Self { state: SlipState::Normal, buf: [0; N], rmax: 0, rpos: 0, wpos: 0 }
+		Self {
+			state: SlipState::Normal,
+			buf: [0; N],
+			rmax: 0,
+			rpos: 0,
+			wpos: 0,
+			stats: Stats::default(),
+		}
 	}
 
-	fn push_byte(&mut self, byte: u8, buf: &mut [u8]) -> io::Result<()> {
+	pub fn stats(&self) -> Stats {
+		self.stats
+	}
+
+	fn push_byte(&mut self, byte: u8, buf: &mut [u8]) {
 		if self.wpos >= buf.len() {
-			self.wpos = 0;
-			return Err(io::Error::new(io::ErrorKind::InvalidData, "push_byte overflow"));
+			self.stats.overflows += 1;
+			self.state = SlipState::Discard;
+			return;
 		}
 		buf[self.wpos] = byte;
 		self.wpos += 1;
-		Ok(())
+		self.state = SlipState::Normal;
 	}
 
+	/// Returns the next frame, or `None` when no complete frame is available yet.
+	///
+	/// Bad frames are dropped and counted, decoding resumes after the next `END`; only the
+	/// errors of the transport are returned.
 	pub fn read<'a>(
 		&mut self,
-		port: &mut Box<dyn SerialPort>,
+		port: &mut dyn Transport,
 		buf: &'a mut [u8],
 	) -> io::Result<Option<&'a [u8]>> {
 		loop {
 			if self.rpos >= self.rmax {
 				self.rpos = 0;
 				match port.read(&mut self.buf) {
-					Ok(n) => self.rmax = n,
-					Err(e) if e.kind() == io::ErrorKind::TimedOut => self.rmax = 0,
+					Ok(n) => {
+						self.rmax = n;
+						self.stats.bytes += n as u64;
+					}
+					Err(e)
+						if e.kind() == io::ErrorKind::TimedOut
+							|| e.kind() == io::ErrorKind::WouldBlock =>
+					{
+						self.rmax = 0
+					}
 					Err(e) => return Err(e),
 				}
 				if self.rmax == 0 {
@@ -54,38 +94,97 @@ impl<const N: usize> Slip<N> {
 			}
 			while self.rpos < self.rmax {
 				let byte = self.buf[self.rpos];
-				match self.state {
-					SlipState::Normal => match byte {
-						END if self.wpos > 1 => {
-							self.rpos += 1;
-							let end = self.wpos;
-							self.wpos = 0;
-							return Ok(Some(&buf[..end]));
-						}
-						END => {
-							return Err(io::Error::new(io::ErrorKind::InvalidData, "empty command"))
-						}
-						ESC => {
-							self.state = SlipState::Escape;
-							self.rpos += 1;
-							continue;
-						}
-						_ => self.push_byte(byte, buf)?,
-					},
-					SlipState::Escape => match byte {
-						ESC_END => self.push_byte(END, buf)?,
-						ESC_ESC => self.push_byte(ESC, buf)?,
-						_ => {
-							return Err(io::Error::new(
-								io::ErrorKind::InvalidData,
-								format!("invalid escape sequence: {:02x}", byte),
-							))
+				self.rpos += 1;
+				match (&self.state, byte) {
+					(SlipState::Normal, END) if self.wpos > 1 => {
+						let end = self.wpos;
+						self.wpos = 0;
+						self.stats.frames += 1;
+						return Ok(Some(&buf[..end]));
+					}
+					(SlipState::Normal, END) => {
+						// an END alone is commonly sent to flush line noise, it is not an error
+						if self.wpos > 0 {
+							self.stats.malformed += 1;
 						}
-					},
+						self.wpos = 0;
+					}
+					(SlipState::Normal, ESC) => self.state = SlipState::Escape,
+					(SlipState::Normal, _) => self.push_byte(byte, buf),
+					(SlipState::Escape, ESC_END) => self.push_byte(END, buf),
+					(SlipState::Escape, ESC_ESC) => self.push_byte(ESC, buf),
+					(SlipState::Escape, END) => {
+						self.stats.malformed += 1;
+						self.state = SlipState::Normal;
+						self.wpos = 0;
+					}
+					(SlipState::Escape, _) => {
+						self.stats.malformed += 1;
+						self.state = SlipState::Discard;
+					}
+					(SlipState::Discard, END) => {
+						self.state = SlipState::Normal;
+						self.wpos = 0;
+					}
+					(SlipState::Discard, _) => {}
 				}
-				self.state = SlipState::Normal;
-				self.rpos += 1;
 			}
 		}
 	}
 }
+
+pub fn encode(frame: &[u8], out: &mut Vec<u8>) {
+	for &byte in frame {
+		match byte {
+			END => out.extend_from_slice(&[ESC, ESC_END]),
+			ESC => out.extend_from_slice(&[ESC, ESC_ESC]),
+			_ => out.push(byte),
+		}
+	}
+	out.push(END);
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::transport::Memory;
+
+	fn read_all(slip: &mut Slip<16>, port: &mut Memory) -> Vec<Vec<u8>> {
+		let mut frames = Vec::new();
+		let mut buf = [0; 4];
+		while let Some(frame) = slip.read(port, &mut buf).unwrap() {
+			frames.push(frame.to_vec());
+		}
+		frames
+	}
+
+	#[test]
+	fn resyncs_after_a_bad_escape() {
+		let (mut device, mut host) = Memory::pair("test");
+		device.write(&[1, ESC, 7, 2, END, 3, 4, END]).unwrap();
+		device.write(&[5, ESC, END, 6, 7, END]).unwrap();
+		let mut slip = Slip::new();
+		assert_eq!(read_all(&mut slip, &mut host), [vec![3, 4], vec![6, 7]]);
+		assert_eq!(slip.stats().malformed, 2);
+	}
+
+	#[test]
+	fn skips_empty_and_short_frames() {
+		let (mut device, mut host) = Memory::pair("test");
+		device.write(&[END, END, 1, END, 2, 3, END]).unwrap();
+		let mut slip = Slip::new();
+		assert_eq!(read_all(&mut slip, &mut host), [vec![2, 3]]);
+		// flushing ENDs are fine, a single byte is not a frame
+		assert_eq!(slip.stats().malformed, 1);
+	}
+
+	#[test]
+	fn drops_the_frames_that_do_not_fit() {
+		let (mut device, mut host) = Memory::pair("test");
+		device.write(&[1, 2, 3, 4, 5, 6, END, 7, 8, END]).unwrap();
+		let mut slip = Slip::new();
+		assert_eq!(read_all(&mut slip, &mut host), [vec![7, 8]]);
+		let stats = slip.stats();
+		assert_eq!((stats.frames, stats.overflows, stats.malformed), (1, 1, 0));
+	}
+}
@@ -0,0 +1,88 @@
+use std::{
+	sync::mpsc,
+	time::{Duration, Instant},
+};
+
+use crate::{
+	draw::{Context, LINE_HEIGHT},
+	font,
+};
+
+/// How long a message stays on the screen.
+const DURATION: Duration = Duration::from_secs(3);
+
+/// A line at the bottom of the screen telling what was just done, like the files saved. The
+/// threads working in the background send their messages through `sender`.
+pub struct Status {
+	sender: mpsc::Sender<String>,
+	receiver: mpsc::Receiver<String>,
+	shown: Option<(String, Instant)>,
+}
+
+impl Status {
+	pub fn new() -> Self {
+		let (sender, receiver) = mpsc::channel();
+		Self { sender, receiver, shown: None }
+	}
+
+	pub fn sender(&self) -> mpsc::Sender<String> {
+		self.sender.clone()
+	}
+
+	pub fn show(&mut self, message: String) {
+		self.shown = Some((message, Instant::now()));
+	}
+
+	/// Takes the messages sent since the last call and forgets the one shown for long enough.
+	/// Returns true if the line changed.
+	pub fn update(&mut self) -> bool {
+		let mut changed = false;
+		while let Ok(message) = self.receiver.try_recv() {
+			self.show(message);
+			changed = true;
+		}
+		if self.shown.as_ref().is_some_and(|(_, since)| since.elapsed() > DURATION) {
+			self.shown = None;
+			changed = true;
+		}
+		changed
+	}
+
+	pub fn message(&self) -> Option<&str> {
+		self.shown.as_ref().map(|(message, _)| message.as_str())
+	}
+
+	/// Draws the line over the bottom of the screen at `origin`, cut to its width.
+	pub fn draw(&self, ctx: &mut Context<'_, '_, '_>, origin: (i32, i32)) -> Result<(), String> {
+		if let Some(message) = self.message() {
+			let width = ctx.screen.width;
+			let len = (width as i32 / font::CHAR_WIDTH - 1).max(0) as usize;
+			let y = origin.1 + ctx.screen.height as i32 - LINE_HEIGHT - 1;
+			ctx.draw_rect((origin.0, y, width, LINE_HEIGHT as u32 + 1), ctx.theme.screen)?;
+			let fg = ctx.theme.text_info;
+			let text: String = message.chars().take(len).collect();
+			ctx.draw_str(&text, origin.0 + 2, y, fg, fg)?;
+		}
+		Ok(())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn shows_the_last_message_sent() {
+		let mut status = Status::new();
+		assert!(!status.update());
+		let sender = status.sender();
+		sender.send("one".to_string()).unwrap();
+		sender.send("two".to_string()).unwrap();
+		assert!(status.update());
+		assert_eq!(status.message(), Some("two"));
+		assert!(!status.update());
+		status.shown.as_mut().unwrap().1 -= DURATION * 2;
+		assert!(status.update());
+		assert_eq!(status.message(), None);
+	}
+}
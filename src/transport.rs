@@ -0,0 +1,326 @@
+use serialport::SerialPort;
+use std::{
+	collections::VecDeque,
+	fs::{File, OpenOptions},
+	io::{self, Read, Write},
+	net::{Shutdown, TcpStream},
+	sync::{
+		atomic::{self, AtomicBool},
+		mpsc, Arc, Mutex,
+	},
+	thread,
+	time::Duration,
+};
+
+const SERIAL_BAUD_RATE: u32 = 115200;
+const TIMEOUT: Duration = Duration::from_millis(1);
+const PIPE_CHUNK: usize = 1024;
+
+/// A byte stream connecting rm8 to an M8.
+///
+/// `read` must not block: when no data is available it returns `Ok(0)` or an error of kind
+/// `TimedOut` or `WouldBlock`. A lost connection is reported as `BrokenPipe`.
+pub trait Transport {
+	fn read(&mut self, buf: &mut [u8]) -> io::Result<usize>;
+	fn write(&mut self, buf: &[u8]) -> io::Result<usize>;
+	fn name(&self) -> Option<String>;
+	fn close(&mut self) -> io::Result<()>;
+}
+
+/// Opens a transport from a device specification.
+///
+/// `tcp:HOST:PORT` connects over TCP, `pipe:INPUT:OUTPUT` uses a pair of files (usually named
+/// pipes) and anything else is taken as the name of a serial port.
+pub fn open(device: &str) -> io::Result<Box<dyn Transport>> {
+	if let Some(addr) = device.strip_prefix("tcp:") {
+		Ok(Box::new(Tcp::connect(addr)?))
+	} else if let Some(files) = device.strip_prefix("pipe:") {
+		match files.split_once(':') {
+			Some((input, output)) => Ok(Box::new(Pipe::open(input, output)?)),
+			None => Err(io::Error::new(io::ErrorKind::InvalidInput, "expected pipe:INPUT:OUTPUT")),
+		}
+	} else {
+		Ok(Box::new(Serial::open(device)?))
+	}
+}
+
+pub struct Serial {
+	port: Box<dyn SerialPort>,
+}
+
+impl Serial {
+	pub fn open(name: &str) -> serialport::Result<Self> {
+		let port = serialport::new(name, SERIAL_BAUD_RATE).timeout(TIMEOUT).open()?;
+		Ok(Self { port })
+	}
+}
+
+impl Transport for Serial {
+	fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+		self.port.read(buf)
+	}
+
+	fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+		self.port.write(buf)
+	}
+
+	fn name(&self) -> Option<String> {
+		self.port.name()
+	}
+
+	fn close(&mut self) -> io::Result<()> {
+		self.port.flush()
+	}
+}
+
+pub struct Tcp {
+	stream: TcpStream,
+	addr: String,
+}
+
+impl Tcp {
+	pub fn connect(addr: &str) -> io::Result<Self> {
+		let stream = TcpStream::connect(addr)?;
+		stream.set_nodelay(true)?;
+		stream.set_read_timeout(Some(TIMEOUT))?;
+		Ok(Self { stream, addr: addr.to_string() })
+	}
+}
+
+impl Transport for Tcp {
+	fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+		match self.stream.read(buf) {
+			Ok(0) if !buf.is_empty() => Err(io::ErrorKind::BrokenPipe.into()),
+			res => res,
+		}
+	}
+
+	fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+		self.stream.write_all(buf).map(|_| buf.len())
+	}
+
+	fn name(&self) -> Option<String> {
+		Some(format!("tcp:{}", self.addr))
+	}
+
+	fn close(&mut self) -> io::Result<()> {
+		self.stream.shutdown(Shutdown::Both)
+	}
+}
+
+/// Reads from any blocking reader (a named pipe, the output of a child process, ...) on a
+/// background thread so that `read` never blocks, and writes to any writer.
+pub struct Pipe {
+	receiver: mpsc::Receiver<Vec<u8>>,
+	pending: VecDeque<u8>,
+	writer: Box<dyn Write>,
+	eof: bool,
+	name: String,
+}
+
+impl Pipe {
+	pub fn new<R, W>(name: &str, reader: R, writer: W) -> Self
+	where
+		R: Read + Send + 'static,
+		W: Write + 'static,
+	{
+		let (sender, receiver) = mpsc::channel();
+		let mut reader = reader;
+		thread::spawn(move || {
+			let mut buf = [0; PIPE_CHUNK];
+			loop {
+				match reader.read(&mut buf) {
+					Ok(0) => break,
+					Ok(n) => {
+						if sender.send(buf[..n].to_vec()).is_err() {
+							break;
+						}
+					}
+					Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+					Err(_) => break,
+				}
+			}
+		});
+		Self {
+			receiver,
+			pending: VecDeque::new(),
+			writer: Box::new(writer),
+			eof: false,
+			name: name.to_string(),
+		}
+	}
+
+	pub fn open(input: &str, output: &str) -> io::Result<Self> {
+		// open the output first so that two processes opening the same pair of fifos in
+		// opposite order do not block each other
+		let writer = OpenOptions::new().write(true).open(output)?;
+		let reader = File::open(input)?;
+		Ok(Self::new(&format!("pipe:{}:{}", input, output), reader, writer))
+	}
+}
+
+impl Transport for Pipe {
+	fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+		loop {
+			match self.receiver.try_recv() {
+				Ok(bytes) => self.pending.extend(bytes),
+				Err(mpsc::TryRecvError::Empty) => break,
+				Err(mpsc::TryRecvError::Disconnected) => {
+					self.eof = true;
+					break;
+				}
+			}
+		}
+		if self.pending.is_empty() && self.eof {
+			return Err(io::ErrorKind::BrokenPipe.into());
+		}
+		let n = buf.len().min(self.pending.len());
+		for (dst, src) in buf.iter_mut().zip(self.pending.drain(..n)) {
+			*dst = src;
+		}
+		Ok(n)
+	}
+
+	fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+		self.writer.write_all(buf)?;
+		self.writer.flush()?;
+		Ok(buf.len())
+	}
+
+	fn name(&self) -> Option<String> {
+		Some(self.name.clone())
+	}
+
+	fn close(&mut self) -> io::Result<()> {
+		self.writer.flush()
+	}
+}
+
+/// One end of an in-process connection, see `Memory::pair`.
+pub struct Memory {
+	rx: Arc<Mutex<VecDeque<u8>>>,
+	tx: Arc<Mutex<VecDeque<u8>>>,
+	closed: Arc<AtomicBool>,
+	name: String,
+}
+
+impl Memory {
+	/// Creates two connected ends: whatever is written to one can be read from the other.
+	pub fn pair(name: &str) -> (Self, Self) {
+		let a = Arc::new(Mutex::new(VecDeque::new()));
+		let b = Arc::new(Mutex::new(VecDeque::new()));
+		let closed = Arc::new(AtomicBool::new(false));
+		(
+			Self { rx: a.clone(), tx: b.clone(), closed: closed.clone(), name: name.to_string() },
+			Self { rx: b, tx: a, closed, name: name.to_string() },
+		)
+	}
+
+	fn is_closed(&self) -> bool {
+		self.closed.load(atomic::Ordering::SeqCst)
+	}
+}
+
+impl Transport for Memory {
+	fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+		let mut rx = self.rx.lock().map_err(|_| io::Error::from(io::ErrorKind::BrokenPipe))?;
+		if rx.is_empty() && self.is_closed() {
+			return Err(io::ErrorKind::BrokenPipe.into());
+		}
+		let n = buf.len().min(rx.len());
+		for (dst, src) in buf.iter_mut().zip(rx.drain(..n)) {
+			*dst = src;
+		}
+		Ok(n)
+	}
+
+	fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+		if self.is_closed() {
+			return Err(io::ErrorKind::BrokenPipe.into());
+		}
+		let mut tx = self.tx.lock().map_err(|_| io::Error::from(io::ErrorKind::BrokenPipe))?;
+		tx.extend(buf);
+		Ok(buf.len())
+	}
+
+	fn name(&self) -> Option<String> {
+		Some(format!("mem:{}", self.name))
+	}
+
+	fn close(&mut self) -> io::Result<()> {
+		self.closed.store(true, atomic::Ordering::SeqCst);
+		Ok(())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::slip::{self, Slip};
+
+	#[test]
+	fn memory_carries_bytes_both_ways() {
+		let (mut a, mut b) = Memory::pair("test");
+		assert_eq!(a.write(b"ER").unwrap(), 2);
+		assert_eq!(b.write(&[1, 2, 3]).unwrap(), 3);
+		let mut buf = [0; 8];
+		assert_eq!(b.read(&mut buf).unwrap(), 2);
+		assert_eq!(&buf[..2], b"ER");
+		assert_eq!(a.read(&mut buf).unwrap(), 3);
+		assert_eq!(&buf[..3], &[1, 2, 3]);
+		assert_eq!(a.name().as_deref(), Some("mem:test"));
+	}
+
+	#[test]
+	fn memory_read_does_not_block() {
+		let (mut a, mut b) = Memory::pair("test");
+		let mut buf = [0; 2];
+		assert_eq!(a.read(&mut buf).unwrap(), 0);
+		b.write(&[1, 2, 3]).unwrap();
+		assert_eq!(a.read(&mut buf).unwrap(), 2);
+		assert_eq!(a.read(&mut buf).unwrap(), 1);
+		assert_eq!(buf[0], 3);
+		assert_eq!(a.read(&mut buf).unwrap(), 0);
+	}
+
+	#[test]
+	fn memory_close_breaks_the_pipe_once_drained() {
+		let (mut a, mut b) = Memory::pair("test");
+		b.write(&[1]).unwrap();
+		b.close().unwrap();
+		assert_eq!(b.write(&[2]).unwrap_err().kind(), io::ErrorKind::BrokenPipe);
+		let mut buf = [0; 4];
+		assert_eq!(a.read(&mut buf).unwrap(), 1);
+		assert_eq!(a.read(&mut buf).unwrap_err().kind(), io::ErrorKind::BrokenPipe);
+		assert_eq!(a.write(&[3]).unwrap_err().kind(), io::ErrorKind::BrokenPipe);
+	}
+
+	#[test]
+	fn slip_reads_frames_over_memory() {
+		let (mut device, mut host) = Memory::pair("test");
+		let mut bytes = Vec::new();
+		slip::encode(&[0xfb, 0xc0, 0xdb], &mut bytes);
+		slip::encode(&[0xfe, 1, 2, 3, 4], &mut bytes);
+		// the first frame comes in two parts, the second one with it
+		device.write(&bytes[..3]).unwrap();
+		let mut slip = Slip::<4>::new();
+		let mut buf = [0; 16];
+		assert_eq!(slip.read(&mut host, &mut buf).unwrap(), None);
+		device.write(&bytes[3..]).unwrap();
+		assert_eq!(slip.read(&mut host, &mut buf).unwrap(), Some(&[0xfb, 0xc0, 0xdb][..]));
+		assert_eq!(slip.read(&mut host, &mut buf).unwrap(), Some(&[0xfe, 1, 2, 3, 4][..]));
+		assert_eq!(slip.read(&mut host, &mut buf).unwrap(), None);
+		let stats = slip.stats();
+		assert_eq!((stats.bytes, stats.frames), (bytes.len() as u64, 2));
+	}
+
+	#[test]
+	fn slip_returns_the_errors_of_the_transport() {
+		let (mut device, mut host) = Memory::pair("test");
+		device.close().unwrap();
+		let mut slip = Slip::<4>::new();
+		let mut buf = [0; 16];
+		let err = slip.read(&mut host, &mut buf).unwrap_err();
+		assert_eq!(err.kind(), io::ErrorKind::BrokenPipe);
+	}
+}
@@ -0,0 +1,133 @@
+use std::{
+	fs::File,
+	io::{self, BufWriter, Seek, SeekFrom, Write},
+	path::{Path, PathBuf},
+	sync::{
+		atomic::{AtomicBool, Ordering},
+		Arc, Mutex,
+	},
+	thread,
+	time::Duration,
+};
+
+use crate::ring::Consumer;
+
+/// How often the samples are written.
+const POLL: Duration = Duration::from_millis(10);
+
+/// Writes 16-bit PCM samples to a WAV file on a separate thread.
+///
+/// The samples are popped from a ring buffer, filled by the audio callback while `recording` is
+/// set; the file is completed once it is cleared by `finish`.
+pub struct WavRecorder {
+	thread: thread::JoinHandle<io::Result<()>>,
+	path: PathBuf,
+	recording: Arc<AtomicBool>,
+}
+
+impl WavRecorder {
+	pub fn create(
+		path: &Path,
+		rate: u32,
+		channels: u16,
+		samples: Arc<Mutex<Consumer>>,
+		recording: Arc<AtomicBool>,
+	) -> Result<Self, String> {
+		let file = File::create(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+		{
+			// the samples left from a previous recording are not part of this one
+			let mut samples = samples.lock().map_err(|e| e.to_string())?;
+			let available = samples.available();
+			samples.skip(available);
+		}
+		recording.store(true, Ordering::Release);
+		let flag = recording.clone();
+		let thread = thread::spawn(move || write(file, &samples, &flag, rate, channels));
+		Ok(Self { thread, path: path.to_path_buf(), recording })
+	}
+
+	/// Stops the recording and waits for the pending samples to be written.
+	pub fn finish(self) -> Result<PathBuf, String> {
+		self.recording.store(false, Ordering::Release);
+		match self.thread.join() {
+			Ok(Ok(())) => Ok(self.path),
+			Ok(Err(e)) => Err(format!("{}: {}", self.path.display(), e)),
+			Err(_) => Err("wav writer panicked".to_string()),
+		}
+	}
+}
+
+fn write(
+	file: File,
+	samples: &Mutex<Consumer>,
+	recording: &AtomicBool,
+	rate: u32,
+	channels: u16,
+) -> io::Result<()> {
+	let mut samples = samples.lock().map_err(|_| io::Error::other("audio ring poisoned"))?;
+	let mut writer = BufWriter::new(file);
+	write_header(&mut writer, rate, channels, 0)?;
+	let mut len = 0u32;
+	let mut buf = vec![0; 4096];
+	loop {
+		// checked first so that the samples pushed until then are all written
+		let stopping = !recording.load(Ordering::Acquire);
+		let n = samples.pop(&mut buf);
+		for sample in buf[..n].iter() {
+			writer.write_all(&sample.to_le_bytes())?;
+		}
+		len = len.saturating_add(n as u32 * 2);
+		if n == 0 {
+			if stopping {
+				break;
+			}
+			thread::sleep(POLL);
+		}
+	}
+	writer.seek(SeekFrom::Start(0))?;
+	write_header(&mut writer, rate, channels, len)?;
+	writer.flush()
+}
+
+fn write_header<W: Write>(writer: &mut W, rate: u32, channels: u16, len: u32) -> io::Result<()> {
+	let block_align = channels * 2;
+	writer.write_all(b"RIFF")?;
+	writer.write_all(&len.saturating_add(36).to_le_bytes())?;
+	writer.write_all(b"WAVEfmt ")?;
+	writer.write_all(&16u32.to_le_bytes())?;
+	writer.write_all(&1u16.to_le_bytes())?; // PCM
+	writer.write_all(&channels.to_le_bytes())?;
+	writer.write_all(&rate.to_le_bytes())?;
+	writer.write_all(&(rate * block_align as u32).to_le_bytes())?;
+	writer.write_all(&block_align.to_le_bytes())?;
+	writer.write_all(&16u16.to_le_bytes())?;
+	writer.write_all(b"data")?;
+	writer.write_all(&len.to_le_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::ring;
+
+	#[test]
+	fn writes_the_samples_pushed_while_recording() {
+		let (mut producer, consumer) = ring::ring(64);
+		let consumer = Arc::new(Mutex::new(consumer));
+		let recording = Arc::new(AtomicBool::new(false));
+		// left from before the recording
+		producer.push(&[9, 9]);
+		let path = std::env::temp_dir().join(format!("rm8-test-{}.wav", std::process::id()));
+		let recorder = WavRecorder::create(&path, 44100, 2, consumer, recording.clone()).unwrap();
+		assert!(recording.load(Ordering::Acquire));
+		producer.push(&[1, -1, 2, -2]);
+		assert_eq!(recorder.finish().unwrap(), path);
+		assert!(!recording.load(Ordering::Acquire));
+		let bytes = std::fs::read(&path).unwrap();
+		std::fs::remove_file(&path).unwrap();
+		assert_eq!(&bytes[40..44], &8u32.to_le_bytes());
+		let samples: Vec<i16> =
+			bytes[44..].chunks_exact(2).map(|b| i16::from_le_bytes([b[0], b[1]])).collect();
+		assert_eq!(samples, [1, -1, 2, -2]);
+	}
+}
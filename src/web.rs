@@ -0,0 +1,142 @@
+use std::{
+	io::{self, BufRead, BufReader, Read, Write},
+	net::{TcpListener, TcpStream},
+	sync::{mpsc, Arc},
+	thread,
+	time::Duration,
+};
+
+use crate::{
+	config::Font,
+	font,
+	server::{self, Clients},
+};
+
+const PAGE: &str = include_str!("web.html");
+const MAX_HEADERS: usize = 8192;
+const MAX_BODY: usize = 256;
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+struct Shared {
+	clients: Clients,
+	input: mpsc::Sender<Vec<u8>>,
+	allow_input: bool,
+	glyphs: Vec<u8>,
+}
+
+/// Serves the web viewer, one thread per request:
+/// - `GET /` is the page,
+/// - `GET /font` the glyphs of the font,
+/// - `GET /stream` the SLIP encoded frames of the M8, until the page is closed,
+/// - `POST /input` takes the `C` and `K` commands sent by the page.
+pub fn spawn(
+	listener: TcpListener,
+	clients: Clients,
+	input: mpsc::Sender<Vec<u8>>,
+	allow_input: bool,
+	font_option: Font,
+) {
+	let shared = Arc::new(Shared { clients, input, allow_input, glyphs: glyphs(font_option) });
+	thread::spawn(move || {
+		for stream in listener.incoming().flatten() {
+			let shared = shared.clone();
+			thread::spawn(move || {
+				// the browser going away in the middle of a request is none of our business
+				let _ = handle(stream, &shared);
+			});
+		}
+	});
+}
+
+fn handle(stream: TcpStream, shared: &Shared) -> io::Result<()> {
+	stream.set_read_timeout(Some(TIMEOUT))?;
+	let mut reader = BufReader::new(stream.try_clone()?);
+	let mut stream = stream;
+
+	let mut request = String::new();
+	reader.read_line(&mut request)?;
+	let mut length = 0;
+	let mut size = request.len();
+	loop {
+		let mut header = String::new();
+		size += reader.read_line(&mut header)?;
+		if size > MAX_HEADERS {
+			return respond(&mut stream, "431 Request Header Fields Too Large", "text/plain", b"");
+		}
+		let header = header.trim_end();
+		if header.is_empty() {
+			break;
+		}
+		if let Some((name, value)) = header.split_once(':') {
+			if name.eq_ignore_ascii_case("content-length") {
+				length = value.trim().parse().unwrap_or(0);
+			}
+		}
+	}
+
+	let mut parts = request.split_whitespace();
+	match (parts.next().unwrap_or_default(), parts.next().unwrap_or_default()) {
+		("GET", "/") => respond(&mut stream, "200 OK", "text/html; charset=utf-8", PAGE.as_bytes()),
+		("GET", "/font") => {
+			respond(&mut stream, "200 OK", "application/octet-stream", &shared.glyphs)
+		}
+		("GET", "/stream") => {
+			stream.write_all(
+				b"HTTP/1.1 200 OK\r\n\
+				Content-Type: application/octet-stream\r\n\
+				Cache-Control: no-cache\r\n\
+				Connection: close\r\n\r\n",
+			)?;
+			stream.set_nodelay(true)?;
+			server::add_stream(stream, &shared.clients)?;
+			// a full redraw of the M8 screen for the new viewer
+			let _ = shared.input.send(b"R".to_vec());
+			Ok(())
+		}
+		("POST", "/input") if !shared.allow_input => {
+			respond(&mut stream, "403 Forbidden", "text/plain", b"view only")
+		}
+		("POST", "/input") if length > MAX_BODY => {
+			respond(&mut stream, "413 Payload Too Large", "text/plain", b"")
+		}
+		("POST", "/input") => {
+			let mut body = vec![0; length];
+			reader.read_exact(&mut body)?;
+			server::forward_input(body.as_slice(), &shared.input);
+			respond(&mut stream, "204 No Content", "text/plain", b"")
+		}
+		_ => respond(&mut stream, "404 Not Found", "text/plain", b"not found"),
+	}
+}
+
+fn respond(
+	stream: &mut TcpStream,
+	status: &str,
+	content_type: &str,
+	body: &[u8],
+) -> io::Result<()> {
+	write!(
+		stream,
+		"HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+		status,
+		content_type,
+		body.len()
+	)?;
+	stream.write_all(body)
+}
+
+/// Returns the 128 characters as drawn with the font option, 8 bytes of 8 pixels each, the
+/// least significant bit first and a set bit being transparent.
+fn glyphs(font_option: Font) -> Vec<u8> {
+	let bytes_by_row = (font::WIDTH / 8) as usize;
+	let (chars_by_row, char_height) = (font::CHARS_BY_ROW as usize, font::CHAR_HEIGHT as usize);
+	let mut glyphs = Vec::with_capacity(128 * char_height);
+	for c in 0..128u8 {
+		let glyph = font_option.map(c) as usize;
+		let (col, row) = (glyph % chars_by_row, glyph / chars_by_row);
+		for line in 0..char_height {
+			glyphs.push(font::DATA[(row * char_height + line) * bytes_by_row + col]);
+		}
+	}
+	glyphs
+}